@@ -314,7 +314,6 @@ mod bench_tests {
         })
     }
 
-
     #[bench]
     fn bench_std_btree_map_lookup_20000_u128(b: &mut Bencher) {
         let mut rng = rand::thread_rng();
@@ -402,4 +401,56 @@ mod bench_tests {
             }
         })
     }
+
+    #[bench]
+    fn bench_sokoban_avl_tree_range_100_of_20000_u128(b: &mut Bencher) {
+        let mut rng = rand::thread_rng();
+        let mut buf = vec![0u8; std::mem::size_of::<AVLTreeMap>()];
+        let m = AVLTreeMap::new_from_slice(buf.as_mut_slice());
+        for v in 0..20000 {
+            m.insert(v as u128, rng.gen::<u128>());
+        }
+        b.iter(|| m.range(10000..10100).count())
+    }
+
+    #[bench]
+    fn bench_sokoban_avl_tree_filter_100_of_20000_u128(b: &mut Bencher) {
+        let mut rng = rand::thread_rng();
+        let mut buf = vec![0u8; std::mem::size_of::<AVLTreeMap>()];
+        let m = AVLTreeMap::new_from_slice(buf.as_mut_slice());
+        for v in 0..20000 {
+            m.insert(v as u128, rng.gen::<u128>());
+        }
+        b.iter(|| {
+            m.iter()
+                .filter(|(k, _)| (10000..10100).contains(*k))
+                .count()
+        })
+    }
+
+    #[bench]
+    fn bench_sokoban_red_black_tree_iter_boxed_20000_u128(b: &mut Bencher) {
+        let mut rng = rand::thread_rng();
+        let mut buf = vec![0u8; std::mem::size_of::<RBTree>()];
+        let m = RBTree::new_from_slice(buf.as_mut_slice());
+        for v in 0..20000 {
+            m.insert(v as u128, rng.gen::<u128>());
+        }
+        b.iter(|| {
+            NodeAllocatorMap::iter(&*m)
+                .map(|(_, v)| *v)
+                .fold(0u128, u128::wrapping_add)
+        })
+    }
+
+    #[bench]
+    fn bench_sokoban_red_black_tree_iter_concrete_20000_u128(b: &mut Bencher) {
+        let mut rng = rand::thread_rng();
+        let mut buf = vec![0u8; std::mem::size_of::<RBTree>()];
+        let m = RBTree::new_from_slice(buf.as_mut_slice());
+        for v in 0..20000 {
+            m.insert(v as u128, rng.gen::<u128>());
+        }
+        b.iter(|| m.iter().map(|(_, v)| *v).fold(0u128, u128::wrapping_add))
+    }
 }
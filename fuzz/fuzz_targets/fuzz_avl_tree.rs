@@ -9,5 +9,6 @@ fuzz_target!(|actions: Vec<NodeAllocatorMapAction::<u64, u64>>| {
     let mut keys = Vec::new();
     for action in actions {
         perform_action(&mut tree, &mut keys, action);
+        assert!(tree.is_valid_avl_tree());
     }
 });
@@ -10,5 +10,12 @@ fuzz_target!(|actions: Vec<NodeAllocatorMapAction::<u64, u64>>| {
     for action in actions {
         perform_action(&mut tree, &mut keys, action);
         assert!(tree.is_valid_red_black_tree());
+        let mut sorted_keys = keys.clone();
+        sorted_keys.sort();
+        for (i, key) in sorted_keys.iter().enumerate() {
+            assert_eq!(tree.rank(key), i);
+            assert_eq!(tree.select(i).map(|(k, _)| k), Some(key));
+        }
+        assert!(tree.select(sorted_keys.len()).is_none());
     }
 });
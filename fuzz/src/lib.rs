@@ -14,6 +14,7 @@ pub enum NodeAllocatorMapAction<K: Copy, V: std::fmt::Debug + std::cmp::PartialE
     IterRev,
     IterMut,
     IterMutRev,
+    Clear,
 }
 
 pub fn perform_action<K: Copy, V: std::fmt::Debug + std::cmp::PartialEq + Copy>(
@@ -72,5 +73,13 @@ pub fn perform_action<K: Copy, V: std::fmt::Debug + std::cmp::PartialEq + Copy>(
         }
         NodeAllocatorMapAction::IterMut => for (_k, _v) in tree.iter_mut() {},
         NodeAllocatorMapAction::IterMutRev => for (_k, _v) in tree.iter_mut().rev() {},
+        NodeAllocatorMapAction::Clear => {
+            tree.clear();
+            assert!(tree.is_empty());
+            assert_eq!(tree.iter().count(), 0);
+            for key in keys.drain(..) {
+                assert!(!tree.contains(&key));
+            }
+        }
     }
 }
@@ -0,0 +1,30 @@
+#![no_std]
+
+use sokoban::*;
+
+pub fn exercise_containers() -> usize {
+    let mut deque = Deque::<u64, 8>::new();
+    deque.push_back(1);
+    deque.push_front(0);
+
+    let mut rbt = RedBlackTree::<u64, u64, 8>::new();
+    rbt.insert(1, 1);
+    let mut avl = AVLTree::<u64, u64, 8>::new();
+    avl.insert(1, 1);
+    let mut critbit = Critbit::<u64, 16, 8>::new();
+    critbit.insert(1, 1);
+    let mut hash_table = HashTable::<u64, u64, 4, 8>::new();
+    hash_table.insert(1, 1);
+    let mut hash_set = HashSet::<u64, 4, 8>::new();
+    hash_set.insert(1);
+    let mut heap = Heap::<u64, u64, 8>::new();
+    heap.push(1);
+
+    deque.iter().count()
+        + rbt.iter().count()
+        + avl.iter().count()
+        + critbit.iter().count()
+        + hash_table.iter().count()
+        + hash_set.iter().count()
+        + heap.len()
+}
@@ -1,33 +1,30 @@
+use alloc::{boxed::Box, vec, vec::Vec};
 use bytemuck::{Pod, Zeroable};
-use std::{
+use core::{
     cmp::max,
-    ops::{Index, IndexMut},
+    ops::{Bound, Index, IndexMut, RangeBounds},
 };
 
 use crate::node_allocator::{
     FromSlice, NodeAllocator, NodeAllocatorMap, OrderedNodeAllocatorMap, ZeroCopy, SENTINEL,
 };
 
-// The number of registers (the last register is currently not in use).
+// The number of registers.
 const REGISTERS: usize = 4;
 
 // Enum representing the fields of a node:
 // 0 - left pointer
 // 1 - right pointer
 // 2 - height of the (sub-)tree
-// TODO: add parent reference using the additional register (tree traversal
-// currently does not need this)
+// 3 - parent pointer (SENTINEL for the root)
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Field {
     Left = 0,
     Right = 1,
     Height = 2,
+    Parent = 3,
 }
 
-// Type representing a path entry (parent, branch, child) when
-// traversing the tree.
-type Ancestor = (Option<u32>, Option<Field>, u32);
-
 #[repr(C)]
 #[derive(Default, Copy, Clone)]
 pub struct AVLNode<
@@ -102,7 +99,7 @@ impl<
     > FromSlice for AVLTree<K, V, MAX_SIZE>
 {
     fn new_from_slice(slice: &mut [u8]) -> &mut Self {
-        let tree = Self::load_mut_bytes(slice).unwrap();
+        let tree = Self::try_load_mut_bytes(slice).unwrap();
         tree.initialize();
         tree
     }
@@ -169,17 +166,22 @@ impl<
     }
 
     fn size(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     fn len(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     fn capacity(&self) -> usize {
         MAX_SIZE
     }
 
+    fn clear(&mut self) {
+        self.root = SENTINEL as u64;
+        self.allocator.reset();
+    }
+
     fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&K, &V)> + '_> {
         Box::new(self._iter())
     }
@@ -266,11 +268,25 @@ impl<
             self.allocator.set_register(node, value, register as u32);
 
             if register == Field::Left || register == Field::Right {
+                // keep the parent pointer of the new child in sync
+                self.allocator
+                    .set_register(value, node, Field::Parent as u32);
                 self.update_height(node);
             }
         }
     }
 
+    #[inline(always)]
+    pub fn get_parent(&self, node: u32) -> u32 {
+        self.get_field(node, Field::Parent)
+    }
+
+    #[inline(always)]
+    fn set_root(&mut self, node: u32) {
+        self.root = node as u64;
+        self.allocator.clear_register(node, Field::Parent as u32);
+    }
+
     #[inline(always)]
     fn get_field(&self, node: u32, register: Field) -> u32 {
         self.allocator.get_register(node, register as u32)
@@ -280,13 +296,11 @@ impl<
         let mut reference_node = self.root as u32;
         let new_node = AVLNode::<K, V>::new(key, value);
         if reference_node == SENTINEL {
-            self.root = self.allocator.add_node(new_node) as u64;
-            return Some(self.root as u32);
+            let node = self.allocator.add_node(new_node);
+            self.set_root(node);
+            return Some(node);
         }
 
-        let mut path: Vec<Ancestor> = Vec::with_capacity((self.len() as f64).log2() as usize);
-        path.push((None, None, reference_node));
-
         loop {
             let current_key = self.get_node(reference_node).key;
             let parent = reference_node;
@@ -303,49 +317,21 @@ impl<
             };
 
             if reference_node == SENTINEL {
-                if self.len() >= self.capacity() {
+                if self.allocator.is_full() {
                     return None;
                 }
                 reference_node = self.allocator.add_node(new_node);
                 self.set_field(parent, branch, reference_node);
+                self.rebalance(parent);
                 break;
-            } else {
-                path.push((Some(parent), Some(branch), reference_node));
             }
         }
 
-        self.rebalance(path);
-
         Some(reference_node)
     }
 
     fn _remove(&mut self, key: &K) -> Option<V> {
-        let mut node_index = self.root as u32;
-        if node_index == SENTINEL {
-            return None;
-        }
-
-        let mut path: Vec<Ancestor> = Vec::with_capacity((self.len() as f64).log2() as usize);
-        path.push((None, None, node_index));
-
-        while node_index != SENTINEL {
-            let current_key = self.get_node(node_index).key;
-            let parent = node_index;
-
-            let branch = if *key < current_key {
-                node_index = self.get_field(parent, Field::Left);
-                Field::Left
-            } else if *key > current_key {
-                node_index = self.get_field(parent, Field::Right);
-                Field::Right
-            } else {
-                break;
-            };
-
-            path.push((Some(parent), Some(branch), node_index));
-        }
-        // sanity check: the loop should be stopped by the break statement
-        // node_index == SENTINEL indicates that the key was not found
+        let node_index = self.get_addr(key);
         if node_index == SENTINEL {
             return None;
         }
@@ -353,17 +339,17 @@ impl<
         let value = self.allocator.get(node_index).get_value().value;
         let left = self.get_field(node_index, Field::Left);
         let right = self.get_field(node_index, Field::Right);
+        let parent = self.get_parent(node_index);
+        let branch = self.branch_of(parent, node_index);
 
-        let replacement = if left != SENTINEL && right != SENTINEL {
+        // the lowest node whose subtree changed, from which the tree is rebalanced
+        let (replacement, rebalance_from) = if left != SENTINEL && right != SENTINEL {
             let mut leftmost = right;
             let mut leftmost_parent = SENTINEL;
-            // path to the leftmost descendant
-            let mut inner_path = Vec::with_capacity((self.len() as f64).log2() as usize);
 
             while self.get_field(leftmost, Field::Left) != SENTINEL {
                 leftmost_parent = leftmost;
                 leftmost = self.get_field(leftmost, Field::Left);
-                inner_path.push((Some(leftmost_parent), Some(Field::Left), leftmost));
             }
             if leftmost_parent != SENTINEL {
                 self.set_field(
@@ -378,55 +364,38 @@ impl<
                 self.set_field(leftmost, Field::Right, right);
             }
 
-            let (parent, branch, _) = path.pop().unwrap();
-
-            if let Some(parent) = parent {
-                self.set_field(parent, branch.unwrap(), leftmost);
-            }
-
-            path.push((parent, branch, leftmost));
-            if right != leftmost {
-                path.push((Some(leftmost), Some(Field::Right), right));
-            }
-            // drop the last inner_path element since it references the leftmost node
-            if !inner_path.is_empty() {
-                inner_path.pop();
-            }
-            path.extend(inner_path);
-
-            leftmost
-        } else {
-            let child = if left == SENTINEL && right == SENTINEL {
-                SENTINEL
-            } else if left != SENTINEL {
-                left
+            if leftmost_parent != SENTINEL {
+                (leftmost, leftmost_parent)
             } else {
-                right
-            };
-
-            let (parent, branch, _) = path.pop().unwrap();
-
-            if let Some(parent) = parent {
-                self.set_field(parent, branch.unwrap(), child);
-
-                if child != SENTINEL {
-                    path.push((Some(parent), branch, child));
-                }
+                (leftmost, leftmost)
             }
-
-            child
+        } else {
+            let child = if left != SENTINEL { left } else { right };
+            (child, parent)
         };
 
-        if node_index == self.root as u32 {
-            self.root = replacement as u64;
+        if parent != SENTINEL {
+            self.set_field(parent, branch, replacement);
+        } else {
+            self.set_root(replacement);
         }
 
         self.delete(node_index);
-        self.rebalance(path);
+        self.rebalance(rebalance_from);
 
         Some(value)
     }
 
+    /// Returns the branch of `parent` that holds `child`
+    #[inline(always)]
+    fn branch_of(&self, parent: u32, child: u32) -> Field {
+        if self.get_field(parent, Field::Left) == child {
+            Field::Left
+        } else {
+            Field::Right
+        }
+    }
+
     fn balance_factor(&self, left: u32, right: u32) -> i32 {
         // safe to convert to i32 since height will be at most log2(capacity)
         let left_height = if left != SENTINEL {
@@ -491,13 +460,17 @@ impl<
         self.allocator.clear_register(node, Field::Left as u32);
         self.allocator.clear_register(node, Field::Right as u32);
         self.allocator.clear_register(node, Field::Height as u32);
+        self.allocator.clear_register(node, Field::Parent as u32);
         self.allocator.remove_node(node);
     }
 
-    fn rebalance(&mut self, path: Vec<Ancestor>) {
-        for (parent, branch, child) in path.iter().rev() {
-            let left = self.get_field(*child, Field::Left);
-            let right = self.get_field(*child, Field::Right);
+    /// Restores the balance of `node` and all of its ancestors by walking up the parent pointers
+    fn rebalance(&mut self, mut node: u32) {
+        while node != SENTINEL {
+            let parent = self.get_parent(node);
+            let branch = self.branch_of(parent, node);
+            let left = self.get_field(node, Field::Left);
+            let right = self.get_field(node, Field::Right);
 
             let balance_factor = self.balance_factor(left, right);
 
@@ -508,10 +481,10 @@ impl<
 
                 if left_balance_factor < 0 {
                     let index = self.left_rotate(left);
-                    self.set_field(*child, Field::Left, index);
+                    self.set_field(node, Field::Left, index);
                 }
 
-                Some(self.right_rotate(*child))
+                Some(self.right_rotate(node))
             } else if balance_factor < -1 {
                 let right_left = self.get_field(right, Field::Left);
                 let right_right = self.get_field(right, Field::Right);
@@ -519,25 +492,69 @@ impl<
 
                 if right_balance_factor > 0 {
                     let index = self.right_rotate(right);
-                    self.set_field(*child, Field::Right, index);
+                    self.set_field(node, Field::Right, index);
                 }
 
-                Some(self.left_rotate(*child))
+                Some(self.left_rotate(node))
             } else {
-                self.update_height(*child);
+                self.update_height(node);
                 None
             };
             if let Some(index) = index {
-                if let Some(parent) = parent {
-                    self.set_field(*parent, (*branch).unwrap(), index);
+                if parent != SENTINEL {
+                    self.set_field(parent, branch, index);
                 } else {
-                    self.root = index as u64;
+                    self.set_root(index);
                     self.update_height(index);
                 }
             }
+            node = parent;
         }
     }
 
+    /// Returns true if the keys are in order, every stored height matches the height of its
+    /// subtree, and every balance factor is in `{-1, 0, 1}`
+    pub fn is_valid_avl_tree(&self) -> bool {
+        self._validate_subtree(self.root as u32, None, None)
+            .is_some()
+    }
+
+    /// Returns the height of the subtree rooted at `node` (-1 for an empty subtree), or None if
+    /// the subtree violates an invariant. Keys must lie strictly between `lower` and `upper`.
+    fn _validate_subtree(&self, node: u32, lower: Option<&K>, upper: Option<&K>) -> Option<i32> {
+        if node == SENTINEL {
+            return Some(-1);
+        }
+        let key = &self.get_node(node).key;
+        if lower.is_some_and(|l| key <= l) || upper.is_some_and(|u| key >= u) {
+            std_println!("Invalid AVL Tree: Node {} is out of order", node);
+            return None;
+        }
+        let left_height =
+            self._validate_subtree(self.get_field(node, Field::Left), lower, Some(key))?;
+        let right_height =
+            self._validate_subtree(self.get_field(node, Field::Right), Some(key), upper)?;
+        let height = max(left_height, right_height) + 1;
+        if self.get_field(node, Field::Height) as i32 != height {
+            std_println!(
+                "Invalid AVL Tree: Node {} has height {}, expected {}",
+                node,
+                self.get_field(node, Field::Height),
+                height
+            );
+            return None;
+        }
+        if (left_height - right_height).abs() > 1 {
+            std_println!(
+                "Invalid AVL Tree: Node {} has balance factor {}",
+                node,
+                left_height - right_height
+            );
+            return None;
+        }
+        Some(height)
+    }
+
     pub fn get_addr(&self, key: &K) -> u32 {
         let mut reference_node = self.root as u32;
         if reference_node == SENTINEL {
@@ -599,6 +616,126 @@ impl<
         }
     }
 
+    /// Returns the entry with the smallest key. Unlike `get_min`, this only borrows the tree
+    /// and returns references into the node instead of copies.
+    pub fn min(&self) -> Option<(&K, &V)> {
+        self.entry_at(self.find_min_index())
+    }
+
+    /// Returns the entry with the largest key. Unlike `get_max`, this only borrows the tree
+    /// and returns references into the node instead of copies.
+    pub fn max(&self) -> Option<(&K, &V)> {
+        self.entry_at(self.find_max_index())
+    }
+
+    /// Removes and returns the entry with the smallest key, rebalancing the tree
+    pub fn pop_min(&mut self) -> Option<(K, V)> {
+        self.pop_at(self.find_min_index())
+    }
+
+    /// Removes and returns the entry with the largest key, rebalancing the tree
+    pub fn pop_max(&mut self) -> Option<(K, V)> {
+        self.pop_at(self.find_max_index())
+    }
+
+    fn pop_at(&mut self, node_index: u32) -> Option<(K, V)> {
+        if node_index == SENTINEL {
+            return None;
+        }
+        let key = self.get_node(node_index).key;
+        self._remove(&key).map(|value| (key, value))
+    }
+
+    /// Returns the entry for `key` for in-place lookup, insertion, and modification. Filling a
+    /// vacant entry goes through the regular insertion path, so the tree is rebalanced and a full
+    /// tree rejects the new key.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, MAX_SIZE> {
+        let node_index = self.get_addr(&key);
+        if node_index == SENTINEL {
+            Entry::Vacant(VacantEntry { tree: self, key })
+        } else {
+            Entry::Occupied(OccupiedEntry {
+                tree: self,
+                node_index,
+            })
+        }
+    }
+
+    fn entry_at(&self, node: u32) -> Option<(&K, &V)> {
+        if node == SENTINEL {
+            None
+        } else {
+            let node = self.get_node(node);
+            Some((&node.key, &node.value))
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries with keys in `range`, in order. Both ends
+    /// are found with a single descent from the root, so entries outside of the range are never
+    /// visited.
+    pub fn range<R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (&K, &V)> + '_ {
+        let start = range.start_bound();
+        let end = range.end_bound();
+        let after_start = |key: &K| match start {
+            Bound::Included(k) => key >= k,
+            Bound::Excluded(k) => key > k,
+            Bound::Unbounded => true,
+        };
+        let before_end = |key: &K| match end {
+            Bound::Included(k) => key <= k,
+            Bound::Excluded(k) => key < k,
+            Bound::Unbounded => true,
+        };
+
+        let (fwd_stack, before_first) = self._seek(after_start, Field::Left, Field::Right);
+        let (rev_stack, after_last) = self._seek(before_end, Field::Right, Field::Left);
+        let terminated = match fwd_stack.last() {
+            Some(&first) => !before_end(&self.get_node(first).key),
+            None => true,
+        };
+
+        // Each end stops at the node just outside of the opposite end of the range, as if the
+        // node had already been yielded from that end
+        AVLTreeIterator::<K, V, MAX_SIZE> {
+            tree: self,
+            fwd_stack,
+            fwd_ptr: SENTINEL,
+            fwd_node: before_first,
+            rev_stack,
+            rev_ptr: SENTINEL,
+            rev_node: after_last,
+            terminated,
+        }
+    }
+
+    /// Descends from the root towards the boundary of the keys satisfying `inside`, which must
+    /// hold on one side of the boundary only. Returns the traversal stack of the nodes inside the
+    /// boundary, with the node closest to the boundary on top, along with the closest node
+    /// outside of it.
+    fn _seek(
+        &self,
+        inside: impl Fn(&K) -> bool,
+        towards: Field,
+        away: Field,
+    ) -> (Vec<u32>, Option<u32>) {
+        let mut node = self.root as u32;
+        let mut stack = vec![];
+        let mut outside = None;
+        while node != SENTINEL {
+            if inside(&self.get_node(node).key) {
+                stack.push(node);
+                node = self.get_field(node, towards);
+            } else {
+                outside = Some(node);
+                node = self.get_field(node, away);
+            }
+        }
+        (stack, outside)
+    }
+
     fn _iter(&self) -> AVLTreeIterator<'_, K, V, MAX_SIZE> {
         AVLTreeIterator::<K, V, MAX_SIZE> {
             tree: self,
@@ -655,6 +792,134 @@ impl<
     }
 }
 
+/// A view into a single key of an `AVLTree`, which is either occupied or vacant.
+pub enum Entry<
+    'a,
+    K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+> {
+    Occupied(OccupiedEntry<'a, K, V, MAX_SIZE>),
+    Vacant(VacantEntry<'a, K, V, MAX_SIZE>),
+}
+
+pub struct OccupiedEntry<
+    'a,
+    K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+> {
+    tree: &'a mut AVLTree<K, V, MAX_SIZE>,
+    node_index: u32,
+}
+
+pub struct VacantEntry<
+    'a,
+    K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+> {
+    tree: &'a mut AVLTree<K, V, MAX_SIZE>,
+    key: K,
+}
+
+impl<
+        'a,
+        K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > Entry<'a, K, V, MAX_SIZE>
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value for the key, inserting `default` if the key is missing. Returns `None`
+    /// if the key is missing and the tree is full.
+    pub fn or_insert(self, default: V) -> Option<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value for the key, inserting the result of `f` if the key is missing.
+    /// Returns `None` if the key is missing and the tree is full.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    pub fn or_default(self) -> Option<&'a mut V> {
+        self.or_insert_with(V::default)
+    }
+
+    /// Applies `f` to the value if the key is present.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<
+        'a,
+        K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > OccupiedEntry<'a, K, V, MAX_SIZE>
+{
+    pub fn key(&self) -> &K {
+        &self.tree.get_node(self.node_index).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.tree.get_node(self.node_index).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.tree.get_node_mut(self.node_index).value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.tree.get_node_mut(self.node_index).value
+    }
+
+    /// Replaces the value of the entry, returning the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the tree, returning its value.
+    pub fn remove(self) -> V {
+        let key = *self.key();
+        // The key is present, so removing it always succeeds
+        self.tree._remove(&key).unwrap()
+    }
+}
+
+impl<
+        'a,
+        K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > VacantEntry<'a, K, V, MAX_SIZE>
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts the key with `value`, returning a reference to the value. Returns `None` if the
+    /// tree is full.
+    pub fn insert(self, value: V) -> Option<&'a mut V> {
+        let node_index = self.tree._insert(self.key, value)?;
+        Some(&mut self.tree.get_node_mut(node_index).value)
+    }
+}
+
 pub struct AVLTreeIterator<
     'a,
     K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
@@ -846,3 +1111,302 @@ impl<
         self.get_mut(index).unwrap()
     }
 }
+
+#[test]
+fn test_parent_pointers() {
+    use rand::Rng;
+    type Tree = AVLTree<u64, u64, 1024>;
+    fn assert_parents_consistent(tree: &Tree) {
+        let root = tree.root as u32;
+        if root == SENTINEL {
+            return;
+        }
+        assert_eq!(tree.get_parent(root), SENTINEL);
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for child in [
+                tree.get_field(node, Field::Left),
+                tree.get_field(node, Field::Right),
+            ] {
+                if child != SENTINEL {
+                    assert_eq!(tree.get_parent(child), node);
+                    stack.push(child);
+                }
+            }
+        }
+    }
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    let mut rng = rand::thread_rng();
+    let mut keys = vec![];
+    for _ in 0..5000 {
+        if keys.len() < 1024 && (keys.is_empty() || rng.gen_range(0, 3) > 0) {
+            let k = rng.gen_range(0, 4096);
+            if tree.insert(k, k).is_some() && !keys.contains(&k) {
+                keys.push(k);
+            }
+        } else {
+            let k = keys.swap_remove(rng.gen_range(0, keys.len()));
+            assert_eq!(tree.remove(&k), Some(k));
+        }
+        assert_parents_consistent(tree);
+    }
+}
+
+#[test]
+fn test_is_valid_avl_tree() {
+    use rand::Rng;
+    type Tree = AVLTree<u64, u64, 1024>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert!(tree.is_valid_avl_tree());
+    let mut rng = rand::thread_rng();
+    let mut keys = vec![];
+    for _ in 0..4096 {
+        if keys.len() < 1024 && (keys.is_empty() || rng.gen_range(0, 3) > 0) {
+            let k = rng.gen_range(0, 4096);
+            if tree.insert(k, k).is_some() && !keys.contains(&k) {
+                keys.push(k);
+            }
+        } else {
+            let k = keys.swap_remove(rng.gen_range(0, keys.len()));
+            assert_eq!(tree.remove(&k), Some(k));
+        }
+        assert!(tree.is_valid_avl_tree());
+    }
+
+    // Corrupt a stored height
+    let root = tree.root as u32;
+    let height = tree.get_field(root, Field::Height);
+    tree.set_field(root, Field::Height, height + 1);
+    assert!(!tree.is_valid_avl_tree());
+    tree.set_field(root, Field::Height, height);
+    assert!(tree.is_valid_avl_tree());
+
+    // Break the ordering by swapping the keys of the root and its left child
+    let left = tree.get_field(root, Field::Left);
+    let root_key = tree.get_node(root).key;
+    tree.get_node_mut(root).key = tree.get_node(left).key;
+    tree.get_node_mut(left).key = root_key;
+    assert!(!tree.is_valid_avl_tree());
+}
+
+#[test]
+fn test_range() {
+    use rand::Rng;
+    use std::collections::BTreeMap;
+    type Tree = AVLTree<u64, u64, 512>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.range(..).count(), 0);
+    let mut rng = rand::thread_rng();
+    let mut map = BTreeMap::new();
+    for _ in 0..512 {
+        let k = rng.gen_range(0, 2048);
+        tree.insert(k, k * 2);
+        map.insert(k, k * 2);
+    }
+
+    fn bound(rng: &mut rand::rngs::ThreadRng) -> Bound<u64> {
+        let k = rng.gen_range(0, 2100);
+        match rng.gen_range(0, 3) {
+            0 => Bound::Included(k),
+            1 => Bound::Excluded(k),
+            _ => Bound::Unbounded,
+        }
+    }
+
+    for _ in 0..1000 {
+        let range = (bound(&mut rng), bound(&mut rng));
+        let expected = map
+            .iter()
+            .filter(|(k, _)| range.contains(*k))
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>();
+        let forward = tree.range(range).map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        assert_eq!(forward, expected);
+        let mut backward = tree
+            .range(range)
+            .rev()
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>();
+        backward.reverse();
+        assert_eq!(backward, expected);
+
+        // Alternate between both ends
+        let mut iter = tree.range(range);
+        let (mut front, mut back) = (vec![], vec![]);
+        loop {
+            let item = if rng.gen::<bool>() {
+                iter.next().map(|e| front.push(e))
+            } else {
+                iter.next_back().map(|e| back.push(e))
+            };
+            if item.is_none() {
+                break;
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        let alternating = front.into_iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        assert_eq!(alternating, expected);
+    }
+
+    let (min, max) = (*map.keys().next().unwrap(), *map.keys().last().unwrap());
+    assert_eq!(tree.range(min..min).count(), 0);
+    assert_eq!(tree.range(max + 1..).count(), 0);
+    assert_eq!(tree.range(..min).count(), 0);
+    assert_eq!(
+        tree.range((Bound::Excluded(max), Bound::Included(min)))
+            .count(),
+        0
+    );
+    assert_eq!(tree.range(min..=min).count(), 1);
+    assert_eq!(tree.range(..).count(), map.len());
+}
+
+#[test]
+fn test_min_max() {
+    type Tree = AVLTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.min(), None);
+    assert_eq!(tree.max(), None);
+    for k in [17, 3, 42, 8, 25] {
+        tree.insert(k, k * 10);
+    }
+    let shared = &*tree;
+    assert_eq!(shared.min(), Some((&3, &30)));
+    assert_eq!(shared.max(), Some((&42, &420)));
+    let (min_key, min_value) = shared.min().unwrap();
+    assert!(std::ptr::eq(min_value, shared.get(min_key).unwrap()));
+    assert_eq!(tree.get_min(), Some((3, 30)));
+    assert_eq!(tree.get_max(), Some((42, 420)));
+}
+
+#[test]
+fn test_entry() {
+    type Tree = AVLTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for k in 0..7 {
+        assert_eq!(tree.entry(k).key(), &k);
+        assert_eq!(tree.entry(k).or_insert(k).copied(), Some(k));
+        *tree.entry(k).or_insert(100).unwrap() += 1;
+        assert!(tree.is_valid_avl_tree());
+    }
+    assert_eq!(tree.len(), 7);
+    for k in 0..7 {
+        assert_eq!(tree.get(&k), Some(&(k + 1)));
+    }
+
+    tree.entry(3).and_modify(|v| *v *= 10).or_insert(0);
+    assert_eq!(tree.get(&3), Some(&40));
+    // the last free slot is taken through a vacant entry
+    tree.entry(7).and_modify(|v| *v *= 10).or_insert_with(|| 7);
+    assert_eq!(tree.get(&7), Some(&7));
+    assert!(tree.is_valid_avl_tree());
+
+    // The tree is full, so vacant entries can't be filled
+    assert!(tree.entry(8).or_default().is_none());
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.get(&8), None);
+    assert_eq!(tree.entry(7).or_default(), Some(&mut 7));
+
+    match tree.entry(3) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.insert(1), 40);
+            assert_eq!(entry.remove(), 1);
+        }
+        Entry::Vacant(_) => panic!("expected an occupied entry"),
+    }
+    assert!(matches!(tree.entry(3), Entry::Vacant(_)));
+    assert_eq!(tree.len(), 7);
+    assert!(tree.is_valid_avl_tree());
+}
+
+#[test]
+fn test_pop_min_and_max() {
+    use rand::Rng;
+    type Tree = AVLTree<u64, u64, 256>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.pop_min(), None);
+    assert_eq!(tree.pop_max(), None);
+
+    tree.insert(5, 50);
+    assert_eq!(tree.pop_max(), Some((5, 50)));
+    assert_eq!(tree.root as u32, SENTINEL);
+    assert!(tree.is_empty());
+
+    let mut expected = std::collections::BTreeMap::new();
+    for _ in 0..256 {
+        let (k, v) = (rng.gen_range(0, 1024), rng.gen());
+        tree.insert(k, v);
+        expected.insert(k, v);
+    }
+    while !expected.is_empty() {
+        let popped = if rng.gen::<bool>() {
+            let k = *expected.keys().next().unwrap();
+            assert_eq!(tree.min().map(|(k, _)| *k), Some(k));
+            (tree.pop_min(), expected.remove_entry(&k))
+        } else {
+            let k = *expected.keys().next_back().unwrap();
+            (tree.pop_max(), expected.remove_entry(&k))
+        };
+        assert_eq!(popped.0, popped.1);
+        assert!(tree.is_valid_avl_tree());
+        assert_eq!(tree.len(), expected.len());
+    }
+    assert_eq!(tree.root as u32, SENTINEL);
+}
+
+#[test]
+fn test_clear() {
+    use rand::Rng;
+    type Tree = AVLTree<u64, u64, 128>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for _ in 0..3 {
+        while tree.len() < tree.capacity() {
+            tree.insert(rng.gen(), rng.gen());
+        }
+        for _ in 0..32 {
+            let key = *tree.iter().nth(rng.gen_range(0, tree.len())).unwrap().0;
+            tree.remove(&key);
+        }
+        tree.clear();
+        assert_eq!(tree.root as u32, SENTINEL);
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.iter().count(), 0);
+        assert!(tree.is_valid_avl_tree());
+        // Refill in ascending order, which rotates on almost every insert, so any height left
+        // behind in a reused slot would unbalance the tree
+        for k in 0..tree.capacity() as u64 {
+            tree.insert(k, k);
+            assert!(tree.is_valid_avl_tree());
+        }
+        assert!(tree.iter().map(|(k, _)| *k).eq(0..128));
+        tree.clear();
+    }
+}
+
+#[test]
+fn test_remove_missing_key_prints_nothing() {
+    type Tree = AVLTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    let printed = crate::PRINTED_LINES.with(|lines| lines.get());
+    assert_eq!(tree.remove(&1), None);
+    for k in 0..32 {
+        tree.insert(k * 2, k);
+    }
+    for k in 0..32 {
+        assert_eq!(tree.remove(&(k * 2 + 1)), None);
+    }
+    assert_eq!(tree.remove(&1000), None);
+    assert_eq!(tree.len(), 32);
+    assert_eq!(crate::PRINTED_LINES.with(|lines| lines.get()), printed);
+}
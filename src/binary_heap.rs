@@ -0,0 +1,582 @@
+use crate::error::SokobanError;
+use crate::node_allocator::{FromSlice, ZeroCopy};
+use alloc::vec::Vec;
+use bytemuck::{Pod, Zeroable};
+use core::mem::{align_of, size_of};
+
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct HeapNode<
+    K: Ord + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+> {
+    pub key: K,
+    pub value: V,
+}
+
+unsafe impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+    > Zeroable for HeapNode<K, V>
+{
+}
+unsafe impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+    > Pod for HeapNode<K, V>
+{
+}
+
+impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+    > HeapNode<K, V>
+{
+    pub fn new(key: K, value: V) -> Self {
+        Self { key, value }
+    }
+}
+
+/// Binary heap stored as an implicit tree in a fixed array: the children of the node at index
+/// `i` are at `2 * i + 1` and `2 * i + 2`. A zeroed buffer is a valid empty heap.
+///
+/// The heap is a max-heap by default. With `MIN = true` (see [`MinHeap`]) the order is reversed
+/// and `peek` and `pop` return the smallest key instead.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Heap<
+    K: Ord + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+    const MIN: bool = false,
+> {
+    pub size: u64,
+    /// Keeps `nodes` 16-byte aligned so that u128 keys are supported
+    _padding: u64,
+    nodes: [HeapNode<K, V>; MAX_SIZE],
+}
+
+/// A [`Heap`] that pops the smallest key first
+pub type MinHeap<K, V, const MAX_SIZE: usize> = Heap<K, V, MAX_SIZE, true>;
+
+unsafe impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+        const MIN: bool,
+    > Zeroable for Heap<K, V, MAX_SIZE, MIN>
+{
+}
+unsafe impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+        const MIN: bool,
+    > Pod for Heap<K, V, MAX_SIZE, MIN>
+{
+}
+
+impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+        const MIN: bool,
+    > ZeroCopy for Heap<K, V, MAX_SIZE, MIN>
+{
+}
+
+impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+        const MIN: bool,
+    > FromSlice for Heap<K, V, MAX_SIZE, MIN>
+{
+    fn new_from_slice(slice: &mut [u8]) -> &mut Self {
+        Self::assert_proper_alignment();
+        Self::try_load_mut_bytes(slice).unwrap()
+    }
+}
+
+impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+        const MIN: bool,
+    > Default for Heap<K, V, MAX_SIZE, MIN>
+{
+    fn default() -> Self {
+        Self::assert_proper_alignment();
+        Self {
+            size: 0,
+            _padding: 0,
+            nodes: [HeapNode::default(); MAX_SIZE],
+        }
+    }
+}
+
+impl<
+        K: Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+        const MIN: bool,
+    > Heap<K, V, MAX_SIZE, MIN>
+{
+    const PROPER_ALIGNMENT: () = {
+        assert!(
+            align_of::<HeapNode<K, V>>() <= align_of::<u128>(),
+            "Heap: K and V must not be more strictly aligned than u128"
+        );
+        assert!(
+            size_of::<HeapNode<K, V>>() == size_of::<K>() + size_of::<V>(),
+            "Heap: HeapNode<K, V> must not contain padding"
+        );
+    };
+
+    fn assert_proper_alignment() {
+        // Referencing the constant forces it to be evaluated for this instantiation
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::PROPER_ALIGNMENT;
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        MAX_SIZE
+    }
+
+    /// Pushes `key` with a default value and returns the index it was sifted to, or `None` if the
+    /// heap is full.
+    pub fn push(&mut self, key: K) -> Option<usize> {
+        self.push_with_value(key, V::default())
+    }
+
+    /// Pushes `key` with the payload `value`, which moves with its key and is returned by `pop`.
+    /// Returns the index the node was sifted to, or `None` if the heap is full.
+    pub fn push_with_value(&mut self, key: K, value: V) -> Option<usize> {
+        if self.len() == MAX_SIZE {
+            return None;
+        }
+        let index = self.len();
+        self.nodes[index] = HeapNode::new(key, value);
+        self.size += 1;
+        Some(self.sift_up(index))
+    }
+
+    /// Returns the largest key (the smallest for a min-heap) without removing it.
+    pub fn peek(&self) -> Option<&K> {
+        if self.size == 0 {
+            return None;
+        }
+        Some(&self.nodes[0].key)
+    }
+
+    /// Returns the payload of the top node for in-place modification. Only the value is exposed,
+    /// since changing the key in place could break the heap order.
+    pub fn peek_mut(&mut self) -> Option<&mut V> {
+        if self.size == 0 {
+            return None;
+        }
+        Some(&mut self.nodes[0].value)
+    }
+
+    /// Removes and returns the node with the largest key (the smallest for a min-heap).
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        if self.size == 0 {
+            return None;
+        }
+        let last = self.len() - 1;
+        self.nodes.swap(0, last);
+        self.size -= 1;
+        self.sift_down(0);
+        let node = self.nodes[last];
+        Some((node.key, node.value))
+    }
+
+    /// Replaces the key of the node at array `index` and moves the node to restore the heap
+    /// order. Returns the node's new index, or `None` if `index` is out of bounds.
+    pub fn update_key(&mut self, index: usize, new_key: K) -> Option<usize> {
+        if index >= self.len() {
+            return None;
+        }
+        self.nodes[index].key = new_key;
+        Some(self.restore(index))
+    }
+
+    /// Removes and returns the node at array `index`, or `None` if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Option<(K, V)> {
+        if index >= self.len() {
+            return None;
+        }
+        let last = self.len() - 1;
+        self.nodes.swap(index, last);
+        self.size -= 1;
+        if index < last {
+            // The node moved into `index` came from a different subtree, so it may belong either
+            // above or below its new position
+            self.restore(index);
+        }
+        let node = self.nodes[last];
+        Some((node.key, node.value))
+    }
+
+    /// Returns the nodes in the heap's internal array order, which is not sorted. Use
+    /// `drain_sorted` or `into_sorted_vec` for ordered traversal.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + '_ {
+        self.nodes[..self.len()]
+            .iter()
+            .map(|node| (&node.key, &node.value))
+    }
+
+    /// Returns the nodes sorted by ascending key, like `std::collections::BinaryHeap`.
+    pub fn into_sorted_vec(mut self) -> Vec<(K, V)> {
+        let mut sorted = self.drain_sorted().collect::<Vec<_>>();
+        if !MIN {
+            sorted.reverse();
+        }
+        sorted
+    }
+
+    /// Returns an iterator that pops a node on each call to `next`, so nodes are yielded in pop
+    /// order. Nodes that are not consumed stay in the heap.
+    pub fn drain_sorted(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        core::iter::from_fn(move || self.pop())
+    }
+
+    /// Copies all of `other`'s nodes into this heap, which may have a different capacity.
+    /// If they do not all fit, `SokobanError::Full` is returned and neither heap is modified.
+    /// The nodes are appended and the heap is rebuilt bottom-up, which takes O(n + m) time.
+    pub fn merge<const M: usize>(
+        &mut self,
+        other: &Heap<K, V, M, MIN>,
+    ) -> Result<(), SokobanError> {
+        let (start, extra) = (self.len(), other.len());
+        if extra > self.capacity() - start {
+            return Err(SokobanError::Full);
+        }
+        self.nodes[start..start + extra].copy_from_slice(&other.nodes[..extra]);
+        self.size += extra as u64;
+        for index in (0..self.len() / 2).rev() {
+            self.sift_down(index);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the node at `i` must sit above the node at `j`
+    #[inline(always)]
+    fn is_above(&self, i: usize, j: usize) -> bool {
+        if MIN {
+            self.nodes[i].key < self.nodes[j].key
+        } else {
+            self.nodes[i].key > self.nodes[j].key
+        }
+    }
+
+    /// Moves the node at `index` up or down to its place in the heap and returns its final index.
+    fn restore(&mut self, index: usize) -> usize {
+        match self.sift_up(index) {
+            i if i == index => self.sift_down(index),
+            i => i,
+        }
+    }
+
+    /// Moves the node at `index` towards the root while it belongs above its parent and returns
+    /// its final index.
+    fn sift_up(&mut self, mut index: usize) -> usize {
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if !self.is_above(index, parent) {
+                break;
+            }
+            self.nodes.swap(index, parent);
+            index = parent;
+        }
+        index
+    }
+
+    /// Moves the node at `index` towards the leaves while a child belongs above it and returns its
+    /// final index.
+    fn sift_down(&mut self, mut index: usize) -> usize {
+        let len = self.len();
+        loop {
+            let left = 2 * index + 1;
+            let right = left + 1;
+            let mut top = index;
+            if left < len && self.is_above(left, top) {
+                top = left;
+            }
+            if right < len && self.is_above(right, top) {
+                top = right;
+            }
+            if top == index {
+                return index;
+            }
+            self.nodes.swap(index, top);
+            index = top;
+        }
+    }
+}
+
+#[test]
+fn test_heap_from_slice() {
+    type H = Heap<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<H>()];
+    let heap = H::new_from_slice(buf.as_mut_slice());
+    assert!(heap.is_empty());
+    assert_eq!(heap.capacity(), 64);
+    let keys = (0..64u64).map(|i| (i * 37) % 64).collect::<Vec<_>>();
+    for &k in keys.iter() {
+        assert!(heap.push(k).is_some());
+    }
+    assert_eq!(heap.len(), 64);
+    assert_eq!(heap.push(100), None);
+    assert_eq!(heap.iter().count(), 64);
+
+    // The heap lives in the buffer, so it can be loaded again from the same bytes
+    let heap = H::load_mut_bytes(buf.as_mut_slice()).unwrap();
+    for expected in (0..64u64).rev() {
+        assert_eq!(heap.pop(), Some((expected, 0)));
+    }
+    assert!(heap.is_empty());
+    assert_eq!(heap.pop(), None);
+}
+
+#[test]
+fn test_pop_empty() {
+    let mut heap = Heap::<u64, u64, 8>::new();
+    assert_eq!(heap.pop(), None);
+    assert!(heap.is_empty());
+    for k in [3, 1, 2] {
+        heap.push(k);
+    }
+    assert_eq!(heap.pop(), Some((3, 0)));
+    assert_eq!(heap.pop(), Some((2, 0)));
+    assert_eq!(heap.pop(), Some((1, 0)));
+    assert_eq!(heap.pop(), None);
+    assert_eq!(heap.pop(), None);
+    assert_eq!(heap.len(), 0);
+    assert!(heap.push(4).is_some());
+    assert_eq!(heap.pop(), Some((4, 0)));
+}
+
+#[test]
+fn test_pop_order_matches_std() {
+    use rand::Rng;
+    use std::collections::BinaryHeap;
+    let mut rng = rand::thread_rng();
+    let mut heap = Heap::<u64, u64, 1024>::new();
+    let mut reference = BinaryHeap::new();
+    for _ in 0..8 {
+        // duplicates and interleaved pops exercise the sift-down bounds on stale slots
+        for _ in 0..rng.gen_range(0, heap.capacity() - heap.len() + 1) {
+            let k = rng.gen_range(0, 256);
+            assert!(heap.push(k).is_some());
+            reference.push(k);
+        }
+        for _ in 0..rng.gen_range(0, heap.len() + 1) {
+            assert_eq!(heap.pop().map(|(k, _)| k), reference.pop());
+        }
+        assert_eq!(heap.len(), reference.len());
+    }
+    while let Some(k) = reference.pop() {
+        assert_eq!(heap.pop(), Some((k, 0)));
+    }
+    assert_eq!(heap.pop(), None);
+}
+
+#[test]
+fn test_push_with_value() {
+    let mut heap = Heap::<u64, u64, 32>::new();
+    // (priority, id) pairs where the id is derived from the priority
+    let priorities = [17u64, 3, 25, 8, 11, 30, 1, 22, 14, 5];
+    for &p in priorities.iter() {
+        let index = heap.push_with_value(p, p * 100 + 7).unwrap();
+        assert_eq!(heap.iter().nth(index), Some((&p, &(p * 100 + 7))));
+    }
+    assert!(heap.iter().all(|(k, v)| *v == k * 100 + 7));
+    let mut sorted = priorities.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    for p in sorted {
+        assert_eq!(heap.pop(), Some((p, p * 100 + 7)));
+    }
+    assert_eq!(heap.pop(), None);
+}
+
+#[test]
+fn test_min_and_max_heap() {
+    let keys = [9u64, 4, 7, 1, 8, 2, 6, 3, 5, 0, 4, 7];
+    let mut max_heap = Heap::<u64, u64, 16>::new();
+    let mut min_heap = MinHeap::<u64, u64, 16>::new();
+    assert_eq!(max_heap.peek(), None);
+    assert_eq!(min_heap.peek(), None);
+    for (i, &k) in keys.iter().enumerate() {
+        max_heap.push_with_value(k, i as u64);
+        min_heap.push_with_value(k, i as u64);
+    }
+    assert_eq!(max_heap.peek(), Some(&9));
+    assert_eq!(min_heap.peek(), Some(&0));
+    let mut max_order = vec![];
+    while let Some((k, v)) = max_heap.pop() {
+        assert_eq!(keys[v as usize], k);
+        max_order.push(k);
+    }
+    let mut min_order = vec![];
+    while let Some((k, v)) = min_heap.pop() {
+        assert_eq!(keys[v as usize], k);
+        min_order.push(k);
+    }
+    let mut sorted = keys.to_vec();
+    sorted.sort_unstable();
+    assert_eq!(min_order, sorted);
+    min_order.reverse();
+    assert_eq!(max_order, min_order);
+    assert_eq!(min_heap.pop(), None);
+}
+
+#[test]
+fn test_into_sorted_vec_and_drain_sorted() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let mut max_heap = Heap::<u64, u64, 256>::new();
+    let mut min_heap = MinHeap::<u64, u64, 256>::new();
+    let mut inputs = vec![];
+    for _ in 0..256 {
+        let k = rng.gen_range(0, 64);
+        max_heap.push_with_value(k, k + 1);
+        min_heap.push_with_value(k, k + 1);
+        inputs.push((k, k + 1));
+    }
+    let mut sorted = inputs.clone();
+    sorted.sort_unstable();
+    assert_eq!(max_heap.into_sorted_vec(), sorted);
+    assert_eq!(min_heap.into_sorted_vec(), sorted);
+
+    let mut drained = max_heap.drain_sorted().take(10).collect::<Vec<_>>();
+    assert_eq!(max_heap.len(), 246);
+    drained.extend(max_heap.drain_sorted());
+    assert!(max_heap.is_empty());
+    sorted.reverse();
+    assert_eq!(drained, sorted);
+    sorted.reverse();
+    assert_eq!(min_heap.drain_sorted().collect::<Vec<_>>(), sorted);
+    assert!(min_heap.is_empty());
+}
+
+#[test]
+fn test_peek() {
+    let mut heap = Heap::<u64, u64, 16>::new();
+    assert_eq!(heap.peek(), None);
+    assert_eq!(heap.peek_mut(), None);
+    for k in [3, 11, 5, 8] {
+        heap.push_with_value(k, k * 10);
+    }
+    assert_eq!(heap.peek(), Some(&11));
+    *heap.peek_mut().unwrap() += 1;
+    assert_eq!(heap.pop(), Some((11, 111)));
+    assert_eq!(heap.peek(), Some(&8));
+    while heap.pop().is_some() {}
+    assert_eq!(heap.peek(), None);
+    assert_eq!(heap.peek_mut(), None);
+}
+
+#[test]
+fn test_update_key_and_remove() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for _ in 0..64 {
+        let mut heap = Heap::<u64, u64, 64>::new();
+        let mut reference = vec![];
+        for v in 0..rng.gen_range(1, 64) {
+            let k = rng.gen_range(0, 100);
+            heap.push_with_value(k, v);
+            reference.push((k, v));
+        }
+        for _ in 0..32 {
+            if heap.is_empty() {
+                break;
+            }
+            let index = rng.gen_range(0, heap.len());
+            let (_, &v) = heap.iter().nth(index).unwrap();
+            let position = reference.iter().position(|(_, x)| *x == v).unwrap();
+            if rng.gen::<bool>() {
+                let k = rng.gen_range(0, 100);
+                let new_index = heap.update_key(index, k).unwrap();
+                assert_eq!(heap.iter().nth(new_index), Some((&k, &v)));
+                reference[position].0 = k;
+            } else {
+                assert_eq!(heap.remove(index), Some(reference.remove(position)));
+            }
+        }
+        assert_eq!(heap.update_key(heap.len(), 0), None);
+        assert_eq!(heap.remove(heap.len()), None);
+        reference.sort_unstable_by_key(|(k, _)| std::cmp::Reverse(*k));
+        let keys = heap.drain_sorted().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys, reference.iter().map(|(k, _)| *k).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_merge() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let a = (0..40).map(|_| rng.gen_range(0, 50)).collect::<Vec<u64>>();
+    let b = (0..24).map(|_| rng.gen_range(0, 50)).collect::<Vec<u64>>();
+    let mut union = a.iter().chain(b.iter()).copied().collect::<Vec<_>>();
+    union.sort_unstable();
+
+    let mut max_heap = Heap::<u64, u64, 64>::new();
+    let mut max_other = Heap::<u64, u64, 32>::new();
+    let mut min_heap = MinHeap::<u64, u64, 64>::new();
+    let mut min_other = MinHeap::<u64, u64, 32>::new();
+    for &k in a.iter() {
+        max_heap.push_with_value(k, k + 100);
+        min_heap.push_with_value(k, k + 100);
+    }
+    for &k in b.iter() {
+        max_other.push_with_value(k, k + 100);
+        min_other.push_with_value(k, k + 100);
+    }
+    max_heap.merge(&max_other).unwrap();
+    min_heap.merge(&min_other).unwrap();
+    assert_eq!(max_heap.len(), 64);
+    assert_eq!(min_heap.len(), 64);
+    assert_eq!(max_other.len(), 24);
+
+    let max_order = max_heap.drain_sorted().collect::<Vec<_>>();
+    let min_order = min_heap.drain_sorted().collect::<Vec<_>>();
+    assert!(max_order.iter().all(|(k, v)| *v == k + 100));
+    assert!(min_order.iter().all(|(k, v)| *v == k + 100));
+    let min_keys = min_order.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    let mut max_keys = max_order.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    assert_eq!(min_keys, union);
+    max_keys.reverse();
+    assert_eq!(max_keys, union);
+
+    // Merging into a heap without enough room fails without touching either heap
+    let mut small = Heap::<u64, u64, 8>::new();
+    for k in 0..5 {
+        small.push(k);
+    }
+    let mut extra = Heap::<u64, u64, 4>::new();
+    for k in 10..14 {
+        extra.push(k);
+    }
+    let before = small.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    assert_eq!(small.merge(&extra), Err(SokobanError::Full));
+    assert_eq!(small.iter().map(|(k, _)| *k).collect::<Vec<_>>(), before);
+    assert_eq!(extra.len(), 4);
+    extra.pop();
+    small.merge(&extra).unwrap();
+    assert_eq!(small.len(), 8);
+    assert_eq!(small.peek(), Some(&12));
+}
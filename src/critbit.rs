@@ -1,24 +1,104 @@
+use alloc::{boxed::Box, vec, vec::Vec};
 use bytemuck::{Pod, Zeroable};
-use std::ops::{Index, IndexMut};
+use core::mem::{align_of, size_of};
+use core::ops::{Index, IndexMut};
 
 use crate::node_allocator::{
     FromSlice, NodeAllocator, NodeAllocatorMap, OrderedNodeAllocatorMap, TreeField as Field,
     ZeroCopy, SENTINEL,
 };
 
+/// Leaf register holding the index of the node that points to the leaf. Register 0 is left
+/// alone because the allocator reuses it for the free list.
+const LEAF_OWNER: u32 = 1;
+
+/// Key type of a `Critbit`. The bits of a key are ordered from the most significant bit of
+/// `u128` keys, or from the most significant bit of the first byte of byte array keys, so the
+/// tree iterates in numeric or lexicographic order respectively.
+///
+/// # Safety
+///
+/// `CritbitNode<K>` is `Pod` for every key type, so implementors must guarantee that the node
+/// has no padding: the size of `K` must be a multiple of 8 bytes and its alignment must not
+/// exceed that of `u128`. Byte arrays only implement the trait for such lengths:
+///
+/// ```compile_fail
+/// use sokoban::critbit::CritbitNode;
+/// // a 12 byte key would leave 4 bytes of padding in the node
+/// let node = CritbitNode::<[u8; 12]>::default();
+/// ```
+pub unsafe trait CritbitKey: Copy + Clone + PartialEq + Pod + Zeroable {
+    /// Number of bits in the key
+    const BITS: u64;
+
+    /// Returns the number of leading bits that are equal in `self` and `other`
+    fn shared_prefix_len(&self, other: &Self) -> u64;
+
+    /// Returns true if the bit at `index` (counting from the most significant bit) is set
+    fn bit(&self, index: u64) -> bool;
+}
+
+unsafe impl CritbitKey for u128 {
+    const BITS: u64 = 128;
+
+    #[inline(always)]
+    fn shared_prefix_len(&self, other: &Self) -> u64 {
+        (self ^ other).leading_zeros() as u64
+    }
+
+    #[inline(always)]
+    fn bit(&self, index: u64) -> bool {
+        (self >> (127 - index)) & 1 != 0
+    }
+}
+
+/// Implements `CritbitKey` for byte arrays whose length is a multiple of 8 and that are `Pod`
+macro_rules! impl_critbit_key_for_byte_array {
+    ($($key_bytes:literal),*) => {
+        $(
+            unsafe impl CritbitKey for [u8; $key_bytes] {
+                const BITS: u64 = $key_bytes * 8;
+
+                #[inline(always)]
+                fn shared_prefix_len(&self, other: &Self) -> u64 {
+                    for (i, (a, b)) in self.iter().zip(other.iter()).enumerate() {
+                        if a != b {
+                            return i as u64 * 8 + (a ^ b).leading_zeros() as u64;
+                        }
+                    }
+                    Self::BITS
+                }
+
+                #[inline(always)]
+                fn bit(&self, index: u64) -> bool {
+                    (self[(index / 8) as usize] >> (7 - index % 8)) & 1 != 0
+                }
+            }
+        )*
+    };
+}
+
+impl_critbit_key_for_byte_array!(8, 16, 24, 32, 48, 64, 96, 128, 256);
+
 #[repr(C)]
-#[derive(Default, Copy, Clone)]
-pub struct CritbitNode {
-    pub key: u128,
+#[derive(Copy, Clone)]
+pub struct CritbitNode<K: CritbitKey = u128> {
+    pub key: K,
     pub prefix_len: u64,
     pub _padding: u64,
 }
 
-unsafe impl Zeroable for CritbitNode {}
-unsafe impl Pod for CritbitNode {}
+unsafe impl<K: CritbitKey> Zeroable for CritbitNode<K> {}
+unsafe impl<K: CritbitKey> Pod for CritbitNode<K> {}
+
+impl<K: CritbitKey> Default for CritbitNode<K> {
+    fn default() -> Self {
+        Self::zeroed()
+    }
+}
 
-impl CritbitNode {
-    pub fn new(prefix_len: u64, key: u128) -> Self {
+impl<K: CritbitKey> CritbitNode<K> {
+    pub fn new(prefix_len: u64, key: K) -> Self {
         Self {
             prefix_len,
             key,
@@ -27,81 +107,121 @@ impl CritbitNode {
     }
 }
 
+/// Critbit tree keyed by `u128` by default, or by any other `CritbitKey` such as `[u8; 32]`.
+///
+/// Keys are stored in the inner nodes, so `K` must not be more strictly aligned than `u128` and
+/// its size must be a multiple of 8 bytes. Each leaf value is stored after 4 `u32` registers, so
+/// `V` must not be more strictly aligned than `u128`, its size must be a multiple of 4 bytes, and
+/// the leaves must fill a whole number of 16 byte words. Otherwise the struct would contain padding, which is not allowed for a `Pod`
+/// type. These conditions are checked at compile time when the tree is constructed:
+///
+/// ```compile_fail
+/// use sokoban::*;
+/// // a single 24 byte leaf leaves 8 bytes of trailing padding
+/// let tree = Critbit::<u64, 2, 1>::new();
+/// ```
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct Critbit<
     V: Default + Copy + Clone + Pod + Zeroable,
     const NUM_NODES: usize,
     const MAX_SIZE: usize,
+    K: CritbitKey = u128,
 > {
     _padding0: u64,
     /// Root node of the critbit tree
     pub root: u32,
     _padding1: u32,
     /// Allocator corresponding to inner nodes and leaf pointers of the critbit
-    node_allocator: NodeAllocator<CritbitNode, NUM_NODES, 4>,
+    node_allocator: NodeAllocator<CritbitNode<K>, NUM_NODES, 4>,
     /// Allocator corresponding to the leaves of the critbit. Note that this
     /// requires 4 registers per leaf to support proper alignment (for aarch64)
     leaves: NodeAllocator<V, MAX_SIZE, 4>,
 }
 
-unsafe impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    Zeroable for Critbit<V, NUM_NODES, MAX_SIZE>
+unsafe impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Zeroable for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
 }
 
-unsafe impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    Pod for Critbit<V, NUM_NODES, MAX_SIZE>
+unsafe impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Pod for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    ZeroCopy for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > ZeroCopy for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    Default for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Default for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
     fn default() -> Self {
+        Self::assert_proper_alignment();
         assert!(NUM_NODES >= 2 * MAX_SIZE);
         Self {
             _padding0: 0,
             root: SENTINEL,
             _padding1: 0,
-            node_allocator: NodeAllocator::<CritbitNode, NUM_NODES, 4>::default(),
+            node_allocator: NodeAllocator::<CritbitNode<K>, NUM_NODES, 4>::default(),
             leaves: NodeAllocator::<V, MAX_SIZE, 4>::default(),
         }
     }
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    FromSlice for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > FromSlice for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
     fn new_from_slice(slice: &mut [u8]) -> &mut Self {
+        Self::assert_proper_alignment();
         assert!(NUM_NODES >= 2 * MAX_SIZE);
-        let tree = Self::load_mut_bytes(slice).unwrap();
+        let tree = Self::try_load_mut_bytes(slice).unwrap();
         tree.initialize();
         tree
     }
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    NodeAllocatorMap<u128, V> for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > NodeAllocatorMap<K, V> for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
-    fn insert(&mut self, key: u128, value: V) -> Option<u32> {
+    fn insert(&mut self, key: K, value: V) -> Option<u32> {
         self._insert(key, value)
     }
 
-    fn remove(&mut self, key: &u128) -> Option<V> {
+    fn remove(&mut self, key: &K) -> Option<V> {
         self._remove(key)
     }
 
-    fn contains(&self, key: &u128) -> bool {
+    fn contains(&self, key: &K) -> bool {
         self.get(key).is_some()
     }
 
-    fn get(&self, key: &u128) -> Option<&V> {
+    fn get(&self, key: &K) -> Option<&V> {
         if self.is_empty() {
             return None;
         }
@@ -116,7 +236,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
                     return None;
                 }
             }
-            let shared_prefix_len = (node.key ^ key).leading_zeros() as u64;
+            let shared_prefix_len = node.key.shared_prefix_len(key);
             if shared_prefix_len >= node.prefix_len {
                 node_index = self.get_child(node.prefix_len, node_index, *key).0;
                 continue;
@@ -126,11 +246,11 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         }
     }
 
-    fn get_mut(&mut self, key: &u128) -> Option<&mut V> {
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
         if self.is_empty() {
             return None;
         }
-        let mut node_index = self.root as u32;
+        let mut node_index = self.root;
         loop {
             let node = self.get_node(node_index);
             if !self.is_inner_node(node_index) {
@@ -141,7 +261,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
                     return None;
                 }
             }
-            let shared_prefix_len = (node.key ^ key).leading_zeros() as u64;
+            let shared_prefix_len = node.key.shared_prefix_len(key);
             if shared_prefix_len >= node.prefix_len {
                 node_index = self.get_child(node.prefix_len, node_index, *key).0;
                 continue;
@@ -152,38 +272,48 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
     }
 
     fn size(&self) -> usize {
-        self.leaves.size as usize
+        self.leaves.len()
     }
 
     fn len(&self) -> usize {
-        self.leaves.size as usize
+        self.leaves.len()
     }
 
     fn capacity(&self) -> usize {
         MAX_SIZE
     }
 
-    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&u128, &V)> + '_> {
+    fn clear(&mut self) {
+        self.root = SENTINEL;
+        self.node_allocator.reset();
+        self.leaves.reset();
+    }
+
+    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&K, &V)> + '_> {
         Box::new(self._iter())
     }
 
-    fn iter_mut(&mut self) -> Box<dyn DoubleEndedIterator<Item = (&u128, &mut V)> + '_> {
+    fn iter_mut(&mut self) -> Box<dyn DoubleEndedIterator<Item = (&K, &mut V)> + '_> {
         Box::new(self._iter_mut())
     }
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    OrderedNodeAllocatorMap<u128, V> for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > OrderedNodeAllocatorMap<K, V> for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
     fn get_min_index(&mut self) -> u32 {
-        self.find_min(self.root as u32)
+        self.find_min(self.root)
     }
 
     fn get_max_index(&mut self) -> u32 {
-        self.find_max(self.root as u32)
+        self.find_max(self.root)
     }
 
-    fn get_min(&mut self) -> Option<(u128, V)> {
+    fn get_min(&mut self) -> Option<(K, V)> {
         match self.get_min_index() {
             SENTINEL => None,
             i => {
@@ -194,7 +324,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         }
     }
 
-    fn get_max(&mut self) -> Option<(u128, V)> {
+    fn get_max(&mut self) -> Option<(K, V)> {
         match self.get_max_index() {
             SENTINEL => None,
             i => {
@@ -206,9 +336,49 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
     }
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
+    const PROPER_ALIGNMENT: () = {
+        assert!(
+            align_of::<K>() <= align_of::<u128>(),
+            "Critbit: K must not be more strictly aligned than u128"
+        );
+        assert!(
+            size_of::<CritbitNode<K>>() == size_of::<K>() + 16,
+            "Critbit: the size of K must be a multiple of 8 bytes"
+        );
+        assert!(
+            align_of::<V>() <= align_of::<u128>(),
+            "Critbit: V must not be more strictly aligned than u128"
+        );
+        assert!(
+            size_of::<V>().is_multiple_of(size_of::<u32>()),
+            "Critbit: the size of V must be a multiple of 4 bytes"
+        );
+        assert!(
+            size_of::<NodeAllocator<CritbitNode<K>, NUM_NODES, 4>>().is_multiple_of(16),
+            "Critbit: NUM_NODES * (size_of::<K>() + 32) must be a multiple of 16"
+        );
+        assert!(
+            size_of::<Self>()
+                == 16
+                    + size_of::<NodeAllocator<CritbitNode<K>, NUM_NODES, 4>>()
+                    + size_of::<NodeAllocator<V, MAX_SIZE, 4>>(),
+            "Critbit: MAX_SIZE * (size_of::<V>() + 16) must be a multiple of 16"
+        );
+    };
+
+    fn assert_proper_alignment() {
+        // Referencing the constant forces it to be evaluated for this instantiation
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::PROPER_ALIGNMENT;
+    }
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -234,11 +404,11 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         self.node_allocator.get_register(node, Field::Value as u32) == SENTINEL
     }
 
-    pub fn get_node(&self, node: u32) -> CritbitNode {
+    pub fn get_node(&self, node: u32) -> CritbitNode<K> {
         *self.node_allocator.get(node).get_value()
     }
 
-    pub fn get_key(&self, node: u32) -> &u128 {
+    pub fn get_key(&self, node: u32) -> &K {
         &self.node_allocator.get(node).get_value().key
     }
 
@@ -257,7 +427,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         self.node_allocator.get_register(node, Field::Parent as u32)
     }
 
-    pub fn get_node_mut(&mut self, node: u32) -> &mut CritbitNode {
+    pub fn get_node_mut(&mut self, node: u32) -> &mut CritbitNode<K> {
         self.node_allocator.get_mut(node).get_value_mut()
     }
 
@@ -267,19 +437,19 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
     }
 
     #[inline(always)]
-    fn add_leaf(&mut self, key: u128, value: V) -> (u32, u32) {
-        let node_index = self.node_allocator.add_node(CritbitNode::new(128, key));
+    fn add_leaf(&mut self, key: K, value: V) -> (u32, u32) {
+        let node_index = self.node_allocator.add_node(CritbitNode::new(K::BITS, key));
         let leaf_index = self.leaves.add_node(value);
         self.node_allocator
             .set_register(node_index, leaf_index, Field::Value as u32);
+        self.leaves.set_register(leaf_index, node_index, LEAF_OWNER);
         self.leaves.get_mut(leaf_index).set_value(value);
         (node_index, leaf_index)
     }
 
     #[inline(always)]
-    fn get_child(&self, prefix_len: u64, node_index: u32, search_key: u128) -> (u32, bool) {
-        let crit_bit_mask = (1u128 << 127) >> prefix_len;
-        if (search_key & crit_bit_mask) != 0 {
+    fn get_child(&self, prefix_len: u64, node_index: u32, search_key: K) -> (u32, bool) {
+        if search_key.bit(prefix_len) {
             (self.get_right(node_index), true)
         } else {
             (self.get_left(node_index), false)
@@ -296,6 +466,8 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
             .get_register(node_index, Field::Value as u32);
         self.node_allocator
             .set_register(index, value, Field::Value as u32);
+        // `value` is SENTINEL for inner nodes, in which case this is a no-op
+        self.leaves.set_register(value, index, LEAF_OWNER);
         self.node_allocator
             .connect(index, left, Field::Left as u32, Field::Parent as u32);
         self.node_allocator
@@ -307,7 +479,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
     fn replace_node(
         &mut self,
         node_index: u32,
-        node_contents: &CritbitNode,
+        node_contents: &CritbitNode<K>,
         left: u32,
         right: u32,
     ) {
@@ -332,6 +504,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
                 .clear_register(source, Field::Value as u32);
             self.node_allocator
                 .set_register(target, leaf_index, Field::Value as u32);
+            self.leaves.set_register(leaf_index, target, LEAF_OWNER);
         }
         assert!(self.get_leaf_index(source) == SENTINEL);
         self.node_allocator.connect(
@@ -357,6 +530,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
     fn remove_leaf(&mut self, node_index: u32) -> V {
         let leaf_index = self.get_leaf_index(node_index);
         let value = *self.get_leaf(leaf_index);
+        self.leaves.clear_register(leaf_index, LEAF_OWNER);
         self.node_allocator
             .clear_register(node_index, Field::Value as u32);
         assert!(self.get_leaf_index(node_index) == SENTINEL);
@@ -383,8 +557,8 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         value
     }
 
-    pub fn get_addr(&self, key: u128) -> u32 {
-        let mut node_index = self.root as u32;
+    pub fn get_addr(&self, key: K) -> u32 {
+        let mut node_index = self.root;
         loop {
             let node = self.get_node(node_index);
             if !self.is_inner_node(node_index) {
@@ -394,7 +568,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
                     return SENTINEL;
                 }
             }
-            let shared_prefix_len = (node.key ^ key).leading_zeros() as u64;
+            let shared_prefix_len = node.key.shared_prefix_len(&key);
             if shared_prefix_len >= node.prefix_len {
                 node_index = self.get_child(node.prefix_len, node_index, key).0;
                 continue;
@@ -404,33 +578,119 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         }
     }
 
-    fn _insert(&mut self, key: u128, value: V) -> Option<u32> {
+    /// Returns the stored key that minimizes `stored ^ key` (comparing byte array keys as
+    /// big-endian integers), along with its value.
+    ///
+    /// Every key below an inner node agrees on all bits above its critical bit, so the subtree
+    /// whose critical bit matches `key` always contains the closer keys. Following `key`'s bits
+    /// at each inner node therefore finds the nearest key without any backtracking.
+    pub fn nearest_xor(&self, key: K) -> Option<(K, &V)> {
+        let (key, value) = self.longest_prefix_match(key)?;
+        Some((*key, value))
+    }
+
+    /// Returns the stored entry whose key shares the longest common prefix with `key`, or the
+    /// entry for `key` itself if it is stored. This is the same leaf as the one found by
+    /// `nearest_xor`, since the key with the longest common prefix minimizes the XOR.
+    pub fn longest_prefix_match(&self, key: K) -> Option<(&K, &V)> {
         if self.root == SENTINEL {
-            let (node_index, _leaf_index) = self.add_leaf(key, value);
-            self.root = node_index;
-            return Some(self.root);
-        }
-        // Return None if the tree is filled up
-        if self.len() >= self.capacity() {
             return None;
         }
         let mut node_index = self.root;
+        while self.is_inner_node(node_index) {
+            let prefix_len = self.get_node(node_index).prefix_len;
+            node_index = self.get_child(prefix_len, node_index, key).0;
+        }
+        Some((
+            self.get_key(node_index),
+            self.get_leaf(self.get_leaf_index(node_index)),
+        ))
+    }
+
+    /// Returns the entries with keys in `[lo, hi]`, in order. Subtrees whose prefix lies entirely
+    /// below `lo` or above `hi` are skipped without being visited.
+    pub fn range(&self, lo: K, hi: K) -> impl Iterator<Item = (&K, &V)> + '_ {
+        let mut stack = vec![];
+        if self.root != SENTINEL && self._overlaps(self.root, &lo, &hi) {
+            stack.push(self.root);
+        }
+        core::iter::from_fn(move || {
+            while let Some(node_index) = stack.pop() {
+                if !self.is_inner_node(node_index) {
+                    return Some((
+                        self.get_key(node_index),
+                        self.get_leaf(self.get_leaf_index(node_index)),
+                    ));
+                }
+                for child in [self.get_right(node_index), self.get_left(node_index)] {
+                    if self._overlaps(child, &lo, &hi) {
+                        stack.push(child);
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Returns true if some key with the prefix of `node_index` lies in `[lo, hi]`. A leaf's prefix
+    /// is its whole key.
+    fn _overlaps(&self, node_index: u32, lo: &K, hi: &K) -> bool {
+        let node = self.get_node(node_index);
+        // The prefix is below `lo` if it has a 0 where it first differs from `lo`
+        let below = node.key.shared_prefix_len(lo);
+        if below < node.prefix_len && !node.key.bit(below) {
+            return false;
+        }
+        // The prefix is above `hi` if it has a 1 where it first differs from `hi`
+        let above = node.key.shared_prefix_len(hi);
+        !(above < node.prefix_len && node.key.bit(above))
+    }
+
+    /// Returns the value for `key`, inserting `V::default()` if the key is missing. Returns
+    /// `None` if the key is missing and the tree is full.
+    pub fn get_or_insert_default(&mut self, key: K) -> Option<&mut V> {
+        let (node_index, _) = self._get_or_insert_with(key, V::default)?;
+        let leaf_index = self.get_leaf_index(node_index);
+        Some(self.get_leaf_mut(leaf_index))
+    }
+
+    fn _insert(&mut self, key: K, value: V) -> Option<u32> {
+        let (node_index, inserted) = self._get_or_insert_with(key, || value)?;
+        if !inserted {
+            // Replace the node with the new value
+            let leaf_index = self.get_leaf_index(node_index);
+            self.replace_leaf(leaf_index, value);
+        }
+        Some(node_index)
+    }
+
+    /// Descends the tree once, returning the index of the leaf node matching `key` if it exists.
+    /// Otherwise a new leaf is created with the value produced by `f`. The boolean flag
+    /// indicates whether a new leaf was inserted. Returns `None` if the key is missing and the
+    /// tree is full.
+    fn _get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<(u32, bool)> {
+        if self.root == SENTINEL {
+            let (node_index, _leaf_index) = self.add_leaf(key, f());
+            self.root = node_index;
+            return Some((self.root, true));
+        }
+        let mut node_index = self.root;
         loop {
             let node = self.get_node(node_index);
             if node.key == key && !self.is_inner_node(node_index) {
-                // Replace the node with the new value
-                let leaf_index = self.get_leaf_index(node_index);
-                self.replace_leaf(leaf_index, value);
-                return Some(node_index);
+                return Some((node_index, false));
             }
-            let shared_prefix_len = (node.key ^ key).leading_zeros() as u64;
+            let shared_prefix_len = node.key.shared_prefix_len(&key);
             if shared_prefix_len >= node.prefix_len {
                 node_index = self.get_child(node.prefix_len, node_index, key).0;
                 continue;
             }
-            let crit_bit_mask: u128 = (1u128 << 127) >> shared_prefix_len;
-            let is_right = (crit_bit_mask & key) != 0;
-            let (node_leaf_index, _leaf_index) = self.add_leaf(key, value);
+            // Return None if the tree is filled up
+            if self.leaves.is_full() {
+                return None;
+            }
+            let is_right = key.bit(shared_prefix_len);
+            let (node_leaf_index, _leaf_index) = self.add_leaf(key, f());
             let moved_node_index = self.duplicate(node_index);
             let new_node = CritbitNode::new(shared_prefix_len, key);
             if is_right {
@@ -438,13 +698,13 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
             } else {
                 self.replace_node(node_index, &new_node, node_leaf_index, moved_node_index);
             }
-            return Some(node_leaf_index);
+            return Some((node_leaf_index, true));
         }
     }
 
-    fn _remove(&mut self, key: &u128) -> Option<V> {
-        let nsize = self.node_allocator.size;
-        let lsize = self.leaves.size;
+    fn _remove(&mut self, key: &K) -> Option<V> {
+        let nsize = self.node_allocator.len();
+        let lsize = self.leaves.len();
         let mut parent = self.root;
         let mut child: u32;
         let mut is_right: bool;
@@ -488,8 +748,8 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         };
         let leaf = self.remove_leaf(child);
         self.migrate(sibling, parent);
-        assert!(nsize - self.node_allocator.size == 2);
-        assert!(lsize - self.leaves.size == 1);
+        assert!(nsize - self.node_allocator.len() == 2);
+        assert!(lsize - self.leaves.len() == 1);
         Some(leaf)
     }
 
@@ -509,9 +769,61 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         node
     }
 
-    fn _iter(&self) -> CritbitIterator<'_, V, NUM_NODES, MAX_SIZE> {
+    /// Returns the entry with the smallest key. Unlike `get_min`, this only borrows the tree
+    /// and returns references instead of copies.
+    pub fn min(&self) -> Option<(&K, &V)> {
+        self.leaf_entry(self.find_min(self.root))
+    }
+
+    /// Returns the entry with the largest key. Unlike `get_max`, this only borrows the tree
+    /// and returns references instead of copies.
+    pub fn max(&self) -> Option<(&K, &V)> {
+        self.leaf_entry(self.find_max(self.root))
+    }
+
+    /// Returns the entry for `key` for in-place lookup, insertion, and modification.
+    pub fn entry(&mut self, key: K) -> Entry<'_, V, NUM_NODES, MAX_SIZE, K> {
+        let node_index = if self.is_empty() {
+            SENTINEL
+        } else {
+            self.get_addr(key)
+        };
+        if node_index == SENTINEL {
+            Entry::Vacant(VacantEntry { tree: self, key })
+        } else {
+            Entry::Occupied(OccupiedEntry {
+                tree: self,
+                node_index,
+            })
+        }
+    }
+
+    /// Returns the key stored for the leaf at `leaf_index` in the leaf allocator, or `None` if
+    /// that leaf is not in use. Every leaf records the index of the node pointing to it, so this
+    /// does not search the tree. Leaves written before owner indices were recorded return `None`.
+    pub fn key_of_leaf(&self, leaf_index: u32) -> Option<K> {
+        let node_index = self
+            .leaves
+            .try_get(leaf_index)?
+            .get_register(LEAF_OWNER as usize);
+        match self.node_allocator.try_get(node_index) {
+            Some(node) if self.get_leaf_index(node_index) == leaf_index => {
+                Some(node.get_value().key)
+            }
+            _ => None,
+        }
+    }
+
+    fn leaf_entry(&self, node: u32) -> Option<(&K, &V)> {
+        if node == SENTINEL {
+            return None;
+        }
+        Some((self.get_key(node), self.get_leaf(self.get_leaf_index(node))))
+    }
+
+    fn _iter(&self) -> CritbitIterator<'_, V, NUM_NODES, MAX_SIZE, K> {
         if self.root == SENTINEL {
-            CritbitIterator::<V, NUM_NODES, MAX_SIZE> {
+            CritbitIterator::<V, NUM_NODES, MAX_SIZE, K> {
                 tree: self,
                 fwd_stack: vec![],
                 fwd_node: None,
@@ -520,7 +832,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
                 terminated: false,
             }
         } else {
-            CritbitIterator::<V, NUM_NODES, MAX_SIZE> {
+            CritbitIterator::<V, NUM_NODES, MAX_SIZE, K> {
                 tree: self,
                 fwd_stack: vec![self.root],
                 fwd_node: None,
@@ -531,10 +843,10 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
         }
     }
 
-    fn _iter_mut(&mut self) -> CritbitIteratorMut<'_, V, NUM_NODES, MAX_SIZE> {
+    fn _iter_mut(&mut self) -> CritbitIteratorMut<'_, V, NUM_NODES, MAX_SIZE, K> {
         let node = self.root;
         if node == SENTINEL {
-            CritbitIteratorMut::<V, NUM_NODES, MAX_SIZE> {
+            CritbitIteratorMut::<V, NUM_NODES, MAX_SIZE, K> {
                 tree: self,
                 fwd_stack: vec![],
                 fwd_node: None,
@@ -543,7 +855,7 @@ impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const M
                 terminated: false,
             }
         } else {
-            CritbitIteratorMut::<V, NUM_NODES, MAX_SIZE> {
+            CritbitIteratorMut::<V, NUM_NODES, MAX_SIZE, K> {
                 tree: self,
                 fwd_stack: vec![node],
                 fwd_node: None,
@@ -560,10 +872,11 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const MAX_NODES: usize,
         const MAX_SIZE: usize,
-    > IntoIterator for &'a Critbit<V, MAX_NODES, MAX_SIZE>
+        K: CritbitKey,
+    > IntoIterator for &'a Critbit<V, MAX_NODES, MAX_SIZE, K>
 {
-    type Item = (&'a u128, &'a V);
-    type IntoIter = CritbitIterator<'a, V, MAX_NODES, MAX_SIZE>;
+    type Item = (&'a K, &'a V);
+    type IntoIter = CritbitIterator<'a, V, MAX_NODES, MAX_SIZE, K>;
 
     fn into_iter(self) -> Self::IntoIter {
         self._iter()
@@ -575,23 +888,163 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const MAX_NODES: usize,
         const MAX_SIZE: usize,
-    > IntoIterator for &'a mut Critbit<V, MAX_NODES, MAX_SIZE>
+        K: CritbitKey,
+    > IntoIterator for &'a mut Critbit<V, MAX_NODES, MAX_SIZE, K>
 {
-    type Item = (&'a u128, &'a mut V);
-    type IntoIter = CritbitIteratorMut<'a, V, MAX_NODES, MAX_SIZE>;
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = CritbitIteratorMut<'a, V, MAX_NODES, MAX_SIZE, K>;
 
     fn into_iter(self) -> Self::IntoIter {
         self._iter_mut()
     }
 }
 
+/// A view into a single key of a `Critbit`, which is either occupied or vacant.
+pub enum Entry<
+    'a,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_NODES: usize,
+    const MAX_SIZE: usize,
+    K: CritbitKey = u128,
+> {
+    Occupied(OccupiedEntry<'a, V, MAX_NODES, MAX_SIZE, K>),
+    Vacant(VacantEntry<'a, V, MAX_NODES, MAX_SIZE, K>),
+}
+
+pub struct OccupiedEntry<
+    'a,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_NODES: usize,
+    const MAX_SIZE: usize,
+    K: CritbitKey = u128,
+> {
+    tree: &'a mut Critbit<V, MAX_NODES, MAX_SIZE, K>,
+    node_index: u32,
+}
+
+pub struct VacantEntry<
+    'a,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_NODES: usize,
+    const MAX_SIZE: usize,
+    K: CritbitKey = u128,
+> {
+    tree: &'a mut Critbit<V, MAX_NODES, MAX_SIZE, K>,
+    key: K,
+}
+
+impl<
+        'a,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Entry<'a, V, MAX_NODES, MAX_SIZE, K>
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value for the key, inserting `default` if the key is missing. Returns `None`
+    /// if the key is missing and the tree is full.
+    pub fn or_insert(self, default: V) -> Option<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value for the key, inserting the result of `f` if the key is missing.
+    /// Returns `None` if the key is missing and the tree is full.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    pub fn or_default(self) -> Option<&'a mut V> {
+        self.or_insert_with(V::default)
+    }
+
+    /// Applies `f` to the value if the key is present.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<
+        'a,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > OccupiedEntry<'a, V, MAX_NODES, MAX_SIZE, K>
+{
+    pub fn key(&self) -> &K {
+        self.tree.get_key(self.node_index)
+    }
+
+    pub fn get(&self) -> &V {
+        self.tree
+            .get_leaf(self.tree.get_leaf_index(self.node_index))
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        let leaf_index = self.tree.get_leaf_index(self.node_index);
+        self.tree.get_leaf_mut(leaf_index)
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        let leaf_index = self.tree.get_leaf_index(self.node_index);
+        self.tree.get_leaf_mut(leaf_index)
+    }
+
+    /// Replaces the value of the entry, returning the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the tree, returning its value.
+    pub fn remove(self) -> V {
+        let key = *self.key();
+        // The key is present, so removing it always succeeds
+        self.tree._remove(&key).unwrap()
+    }
+}
+
+impl<
+        'a,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > VacantEntry<'a, V, MAX_NODES, MAX_SIZE, K>
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts the key with `value`, returning a reference to the value. Returns `None` if the
+    /// tree is full.
+    pub fn insert(self, value: V) -> Option<&'a mut V> {
+        let node_index = self.tree._insert(self.key, value)?;
+        let leaf_index = self.tree.get_leaf_index(node_index);
+        Some(self.tree.get_leaf_mut(leaf_index))
+    }
+}
+
 pub struct CritbitIterator<
     'a,
     V: Default + Copy + Clone + Pod + Zeroable,
     const MAX_NODES: usize,
     const MAX_SIZE: usize,
+    K: CritbitKey = u128,
 > {
-    tree: &'a Critbit<V, MAX_NODES, MAX_SIZE>,
+    tree: &'a Critbit<V, MAX_NODES, MAX_SIZE, K>,
     fwd_stack: Vec<u32>,
     fwd_node: Option<u32>,
     rev_stack: Vec<u32>,
@@ -604,9 +1057,10 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const MAX_NODES: usize,
         const MAX_SIZE: usize,
-    > Iterator for CritbitIterator<'a, V, MAX_NODES, MAX_SIZE>
+        K: CritbitKey,
+    > Iterator for CritbitIterator<'a, V, MAX_NODES, MAX_SIZE, K>
 {
-    type Item = (&'a u128, &'a V);
+    type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
         while !self.terminated && !self.fwd_stack.is_empty() {
@@ -640,7 +1094,8 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const MAX_NODES: usize,
         const MAX_SIZE: usize,
-    > DoubleEndedIterator for CritbitIterator<'a, V, MAX_NODES, MAX_SIZE>
+        K: CritbitKey,
+    > DoubleEndedIterator for CritbitIterator<'a, V, MAX_NODES, MAX_SIZE, K>
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         while !self.terminated && !self.rev_stack.is_empty() {
@@ -674,8 +1129,9 @@ pub struct CritbitIteratorMut<
     V: Default + Copy + Clone + Pod + Zeroable,
     const MAX_NODES: usize,
     const MAX_SIZE: usize,
+    K: CritbitKey = u128,
 > {
-    tree: &'a mut Critbit<V, MAX_NODES, MAX_SIZE>,
+    tree: &'a mut Critbit<V, MAX_NODES, MAX_SIZE, K>,
     fwd_stack: Vec<u32>,
     fwd_node: Option<u32>,
     rev_stack: Vec<u32>,
@@ -688,9 +1144,10 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const MAX_NODES: usize,
         const MAX_SIZE: usize,
-    > Iterator for CritbitIteratorMut<'a, V, MAX_NODES, MAX_SIZE>
+        K: CritbitKey,
+    > Iterator for CritbitIteratorMut<'a, V, MAX_NODES, MAX_SIZE, K>
 {
-    type Item = (&'a u128, &'a mut V);
+    type Item = (&'a K, &'a mut V);
 
     fn next(&mut self) -> Option<Self::Item> {
         while !self.terminated && !self.fwd_stack.is_empty() {
@@ -734,7 +1191,8 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const MAX_NODES: usize,
         const MAX_SIZE: usize,
-    > DoubleEndedIterator for CritbitIteratorMut<'a, V, MAX_NODES, MAX_SIZE>
+        K: CritbitKey,
+    > DoubleEndedIterator for CritbitIteratorMut<'a, V, MAX_NODES, MAX_SIZE, K>
 {
     fn next_back(&mut self) -> Option<Self::Item> {
         while !self.terminated && !self.rev_stack.is_empty() {
@@ -773,20 +1231,448 @@ impl<
     }
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    Index<u128> for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Index<K> for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
     type Output = V;
 
-    fn index(&self, index: u128) -> &Self::Output {
+    fn index(&self, index: K) -> &Self::Output {
         self.get(&index).unwrap()
     }
 }
 
-impl<V: Default + Copy + Clone + Pod + Zeroable, const NUM_NODES: usize, const MAX_SIZE: usize>
-    IndexMut<u128> for Critbit<V, NUM_NODES, MAX_SIZE>
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > IndexMut<K> for Critbit<V, NUM_NODES, MAX_SIZE, K>
 {
-    fn index_mut(&mut self, index: u128) -> &mut Self::Output {
+    fn index_mut(&mut self, index: K) -> &mut Self::Output {
         self.get_mut(&index).unwrap()
     }
 }
+
+#[test]
+fn test_nearest_xor() {
+    use rand::Rng;
+    type Tree = Critbit<u64, 512, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert!(tree.nearest_xor(0).is_none());
+    let mut rng = rand::thread_rng();
+    let mut keys = vec![];
+    for i in 0..256 {
+        // Mix fully random keys with keys that share long prefixes
+        let k = if i % 2 == 0 {
+            rng.gen::<u128>()
+        } else {
+            rng.gen::<u16>() as u128
+        };
+        tree.insert(k, i);
+        keys.push(k);
+    }
+    for _ in 0..1000 {
+        let query = if rng.gen::<bool>() {
+            rng.gen::<u128>()
+        } else {
+            rng.gen::<u16>() as u128
+        };
+        let expected = keys.iter().min_by_key(|k| *k ^ query).unwrap();
+        let (nearest, value) = tree.nearest_xor(query).unwrap();
+        assert_eq!(nearest, *expected);
+        assert_eq!(value, tree.get(&nearest).unwrap());
+    }
+    for k in keys.iter() {
+        assert_eq!(tree.nearest_xor(*k).unwrap().0, *k);
+    }
+}
+
+#[test]
+fn test_get_or_insert_default() {
+    type Tree = Critbit<u64, 16, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    // Insert into an empty tree, then split the root leaf
+    *tree.get_or_insert_default(1).unwrap() += 10;
+    *tree.get_or_insert_default(1 << 100).unwrap() += 20;
+    // Hit an existing leaf
+    *tree.get_or_insert_default(1).unwrap() += 1;
+    for k in 2..8u128 {
+        // Split inner nodes, including ones that share a long prefix with the new key
+        *tree.get_or_insert_default(k << 64 | 1).unwrap() += k as u64;
+        *tree.get_or_insert_default(k << 64 | 1).unwrap() += 1;
+    }
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.get(&1), Some(&11));
+    assert_eq!(tree.get(&(1 << 100)), Some(&20));
+    for k in 2..8u128 {
+        assert_eq!(tree.get(&(k << 64 | 1)), Some(&(k as u64 + 1)));
+    }
+    // The tree is full, so only existing keys can be accessed
+    assert!(tree.get_or_insert_default(9).is_none());
+    assert_eq!(tree.get_or_insert_default(1), Some(&mut 11));
+    assert_eq!(tree.len(), 8);
+}
+
+#[test]
+fn test_iter_empty_and_single() {
+    type Tree = Critbit<u64, 8, 4>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert!(tree.iter().next().is_none());
+    assert!(tree.iter().next_back().is_none());
+    assert!(tree.iter_mut().next().is_none());
+    assert!(tree.iter_mut().next_back().is_none());
+
+    tree.insert(7, 70);
+    assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&7, &70)]);
+    assert_eq!(tree.iter().rev().collect::<Vec<_>>(), vec![(&7, &70)]);
+    tree.iter_mut().for_each(|(_, v)| *v += 1);
+    assert_eq!(tree.iter_mut().rev().count(), 1);
+    assert_eq!(tree.get(&7), Some(&71));
+
+    // Removing the last element leaves an empty iterator again
+    tree.remove(&7);
+    assert!(tree.iter().next().is_none());
+    assert!(tree.iter_mut().next_back().is_none());
+}
+
+#[test]
+fn test_leaf_alignment() {
+    #[repr(C)]
+    #[derive(Default, Copy, Clone, PartialEq, Debug)]
+    struct Large {
+        a: [u128; 4],
+        b: [u64; 3],
+        c: u32,
+        d: [u8; 4],
+    }
+    unsafe impl Zeroable for Large {}
+    unsafe impl Pod for Large {}
+
+    fn check<V: Default + Copy + Clone + Pod + Zeroable + PartialEq + std::fmt::Debug>(
+        make: impl Fn(u128) -> V,
+    ) {
+        type Tree<V> = Critbit<V, 64, 32>;
+        let mut buf = vec![0u128; size_of::<Tree<V>>() / 16];
+        let tree = Tree::<V>::new_from_slice(bytemuck::cast_slice_mut(buf.as_mut_slice()));
+        for k in 0..32 {
+            assert!(tree.insert(k * 7919, make(k)).is_some());
+        }
+        for k in 0..32 {
+            assert_eq!(tree.get(&(k * 7919)), Some(&make(k)));
+        }
+        let tree = Tree::<V>::new();
+        assert!(tree.is_empty());
+    }
+
+    check(|k| k as u64);
+    check(|k| k);
+    check(|k| [k as u32; 3]);
+    check(|k| [k as u8; 12]);
+    check(|k| Large {
+        a: [k; 4],
+        b: [k as u64; 3],
+        c: k as u32,
+        d: [k as u8; 4],
+    });
+}
+
+#[test]
+fn test_byte_array_keys() {
+    use rand::Rng;
+    use std::collections::BTreeMap;
+    type Tree = Critbit<u64, 1024, 512, [u8; 32]>;
+    let mut buf = vec![0u128; size_of::<Tree>() / 16];
+    let tree = Tree::new_from_slice(bytemuck::cast_slice_mut(buf.as_mut_slice()));
+    let mut rng = rand::thread_rng();
+    let mut map = BTreeMap::new();
+    for i in 0..2048u64 {
+        let mut key = [0u8; 32];
+        rng.fill(&mut key[..]);
+        // Share long prefixes between some of the keys
+        if i % 3 == 0 {
+            key[..31].fill(7);
+        }
+        if rng.gen_range(0, 3) > 0 || map.is_empty() {
+            if tree.insert(key, i).is_some() {
+                map.insert(key, i);
+            } else {
+                assert_eq!(map.len(), 512);
+            }
+        } else {
+            let key = *map.keys().nth(rng.gen_range(0, map.len())).unwrap();
+            assert_eq!(tree.remove(&key), map.remove(&key));
+        }
+        assert_eq!(tree.len(), map.len());
+    }
+    // Byte array keys are ordered lexicographically
+    assert!(tree
+        .iter()
+        .map(|(k, v)| (*k, *v))
+        .eq(map.iter().map(|(k, v)| (*k, *v))));
+    assert!(tree
+        .iter()
+        .rev()
+        .map(|(k, _)| *k)
+        .eq(map.keys().rev().copied()));
+    for (k, v) in map.iter() {
+        assert_eq!(tree.get(k), Some(v));
+        assert_eq!(tree.nearest_xor(*k).unwrap().0, *k);
+    }
+    assert_eq!(tree.get(&[0xff; 32]), map.get(&[0xff; 32]));
+}
+
+#[test]
+fn test_byte_array_keys_match_u128_keys() {
+    use rand::Rng;
+    type Tree = Critbit<u64, 512, 256>;
+    type BytesTree = Critbit<u64, 512, 256, [u8; 16]>;
+    let mut buf = vec![0u128; size_of::<Tree>() / 16];
+    let tree = Tree::new_from_slice(bytemuck::cast_slice_mut(buf.as_mut_slice()));
+    let mut bytes_buf = vec![0u128; size_of::<BytesTree>() / 16];
+    let bytes_tree = BytesTree::new_from_slice(bytemuck::cast_slice_mut(bytes_buf.as_mut_slice()));
+    let mut rng = rand::thread_rng();
+    for i in 0..256 {
+        let k = rng.gen::<u128>() >> rng.gen_range(0, 128);
+        tree.insert(k, i);
+        bytes_tree.insert(k.to_be_bytes(), i);
+    }
+    // Big-endian byte keys have the same bit order as the integer keys
+    assert!(tree
+        .iter()
+        .map(|(k, v)| (k.to_be_bytes(), *v))
+        .eq(bytes_tree.iter().map(|(k, v)| (*k, *v))));
+    for _ in 0..256 {
+        let query = rng.gen::<u128>();
+        let (nearest, _) = tree.nearest_xor(query).unwrap();
+        let (nearest_bytes, _) = bytes_tree.nearest_xor(query.to_be_bytes()).unwrap();
+        assert_eq!(nearest.to_be_bytes(), nearest_bytes);
+    }
+}
+
+#[test]
+fn test_get_key_diverging_above_crit_bit() {
+    type Tree = Critbit<u64, 8, 4>;
+    let mut buf = vec![0u128; size_of::<Tree>() / 16];
+    let tree = Tree::new_from_slice(bytemuck::cast_slice_mut(buf.as_mut_slice()));
+    // Both keys share the prefix 0b110, so the root's critical bit is bit 3
+    tree.insert(0b1100 << 124, 1);
+    tree.insert(0b1101 << 124, 2);
+    // This key already differs at bit 0, above the root's critical bit
+    let absent = 0b0100 << 124;
+    assert_eq!(tree.get(&absent), None);
+    assert_eq!(tree.get_mut(&absent), None);
+    assert_eq!(tree.get_addr(absent), SENTINEL);
+    assert!(!tree.contains(&absent));
+    assert_eq!(tree.get(&(0b1101 << 124)), Some(&2));
+}
+
+#[test]
+fn test_longest_prefix_match() {
+    use rand::Rng;
+    type Tree = Critbit<u64, 512, 256>;
+    let mut buf = vec![0u128; size_of::<Tree>() / 16];
+    let tree = Tree::new_from_slice(bytemuck::cast_slice_mut(buf.as_mut_slice()));
+    assert!(tree.longest_prefix_match(0).is_none());
+    tree.insert(5, 50);
+    assert_eq!(tree.longest_prefix_match(u128::MAX), Some((&5, &50)));
+
+    let mut rng = rand::thread_rng();
+    let mut keys = vec![5];
+    for i in 0..255 {
+        // Cover a range of prefix lengths, like a routing table
+        let k = rng.gen::<u128>() >> rng.gen_range(0, 128);
+        if tree.insert(k, i).is_some() && !keys.contains(&k) {
+            keys.push(k);
+        }
+    }
+    for k in keys.iter() {
+        assert_eq!(
+            tree.longest_prefix_match(*k),
+            Some((k, tree.get(k).unwrap()))
+        );
+    }
+    for _ in 0..1000 {
+        let query = rng.gen::<u128>() >> rng.gen_range(0, 128);
+        let (key, value) = tree.longest_prefix_match(query).unwrap();
+        let best = keys
+            .iter()
+            .map(|k| k.shared_prefix_len(&query))
+            .max()
+            .unwrap();
+        assert_eq!(key.shared_prefix_len(&query), best);
+        assert_eq!(tree.get(key), Some(value));
+    }
+}
+
+#[test]
+fn test_range() {
+    use rand::Rng;
+    type Tree = Critbit<u64, 1024, 512>;
+    let mut buf = vec![0u128; size_of::<Tree>() / 16];
+    let tree = Tree::new_from_slice(bytemuck::cast_slice_mut(buf.as_mut_slice()));
+    assert_eq!(tree.range(0, u128::MAX).count(), 0);
+    let mut rng = rand::thread_rng();
+    for i in 0..512 {
+        let k = rng.gen::<u128>() >> rng.gen_range(0, 128);
+        tree.insert(k, i);
+    }
+    let all = tree.iter().collect::<Vec<_>>();
+    let mut keys = all.iter().map(|(k, _)| **k).collect::<Vec<_>>();
+    keys.extend([0, 1, u128::MAX]);
+    for _ in 0..1000 {
+        let mut pick = || {
+            if rng.gen::<bool>() {
+                keys[rng.gen_range(0, keys.len())]
+            } else {
+                rng.gen::<u128>() >> rng.gen_range(0, 128)
+            }
+        };
+        let (lo, hi) = (pick(), pick());
+        let expected = all
+            .iter()
+            .filter(|(k, _)| lo <= **k && **k <= hi)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(tree.range(lo, hi).collect::<Vec<_>>(), expected);
+    }
+    assert_eq!(tree.range(0, u128::MAX).collect::<Vec<_>>(), all);
+}
+
+#[test]
+fn test_clear() {
+    type Tree = Critbit<u64, 32, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for round in 0..3u128 {
+        for k in 0..16u128 {
+            assert!(tree.insert(k * 7 + round, k as u64).is_some());
+        }
+        assert!(tree.insert(1000, 0).is_none());
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.root, SENTINEL);
+        assert_eq!(tree.iter().count(), 0);
+        assert!(!tree.contains(&round));
+    }
+    for k in (0..16u128).rev() {
+        assert!(tree.insert(k, k as u64).is_some());
+    }
+    assert_eq!(tree.len(), tree.capacity());
+    assert_eq!(
+        tree.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+        (0..16).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_min_max() {
+    use rand::Rng;
+    type Tree = Critbit<u64, 512, 256>;
+    let mut rng = rand::thread_rng();
+    let mut tree = Tree::new();
+    assert_eq!(tree.min(), None);
+    assert_eq!(tree.max(), None);
+    let mut keys = vec![];
+    for _ in 0..256 {
+        let k = rng.gen::<u128>();
+        tree.insert(k, k as u64);
+        keys.push(k);
+        let lo = *keys.iter().min().unwrap();
+        let hi = *keys.iter().max().unwrap();
+        assert_eq!(tree.min(), Some((&lo, &(lo as u64))));
+        assert_eq!(tree.max(), Some((&hi, &(hi as u64))));
+    }
+    assert_eq!(tree.min().map(|(k, v)| (*k, *v)), tree.get_min());
+    assert_eq!(tree.max().map(|(k, v)| (*k, *v)), tree.get_max());
+    keys.sort_unstable();
+    tree.remove(&keys[0]);
+    tree.remove(&keys[255]);
+    assert_eq!(tree.min().map(|(k, _)| *k), Some(keys[1]));
+    assert_eq!(tree.max().map(|(k, _)| *k), Some(keys[254]));
+}
+
+#[test]
+fn test_entry() {
+    type Tree = Critbit<u64, 64, 32>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    let keys = [3u128, 1 << 100, 7, 3, 7, 3, u128::MAX];
+    for (i, key) in keys.iter().enumerate() {
+        let len = tree.len();
+        let is_new = !tree.contains(key);
+        let value = tree
+            .entry(*key)
+            .and_modify(|v| *v += 1)
+            .or_insert(0)
+            .unwrap();
+        assert_eq!(
+            *value,
+            keys[..i].iter().filter(|k| *k == key).count() as u64
+        );
+        // Only new keys change the structure of the tree
+        assert_eq!(tree.len(), len + is_new as usize);
+    }
+    assert_eq!(tree.len(), 4);
+    assert_eq!(tree.get(&3), Some(&2));
+    assert_eq!(tree.get(&7), Some(&1));
+
+    match tree.entry(7) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(*entry.key(), 7);
+            assert_eq!(entry.insert(10), 1);
+            assert_eq!(*entry.get(), 10);
+            assert_eq!(entry.remove(), 10);
+        }
+        Entry::Vacant(_) => panic!("expected an occupied entry"),
+    }
+    assert!(!tree.contains(&7));
+    assert_eq!(*tree.entry(7).or_default().unwrap(), 0);
+
+    // A vacant entry cannot be inserted into a full tree
+    let mut k = 1000;
+    while tree.len() < tree.capacity() {
+        tree.entry(k).or_insert(k as u64).unwrap();
+        k += 1;
+    }
+    assert!(tree.entry(k).or_insert(0).is_none());
+    assert!(tree.entry(3).or_insert(0).is_some());
+    assert!(tree.entry(k).and_modify(|v| *v = 1).or_default().is_none());
+}
+
+#[test]
+fn test_key_of_leaf() {
+    use rand::Rng;
+    type Tree = Critbit<u64, 256, 128>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.key_of_leaf(SENTINEL), None);
+    assert_eq!(tree.key_of_leaf(1), None);
+    let mut keys = vec![];
+    for _ in 0..3 {
+        while tree.len() < tree.capacity() {
+            let key = rng.gen::<u128>();
+            tree.insert(key, 0);
+            keys.push(key);
+        }
+        // Removals migrate nodes, which must keep the owner of every remaining leaf current
+        for _ in 0..64 {
+            let key = keys.swap_remove(rng.gen_range(0, keys.len()));
+            let leaf_index = tree.get_leaf_index(tree.get_addr(key));
+            tree.remove(&key);
+            assert_eq!(tree.key_of_leaf(leaf_index), None);
+        }
+        for key in keys.iter() {
+            let leaf_index = tree.get_leaf_index(tree.get_addr(*key));
+            assert_eq!(tree.key_of_leaf(leaf_index), Some(*key));
+        }
+    }
+    assert_eq!(tree.key_of_leaf(129), None);
+}
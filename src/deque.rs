@@ -1,8 +1,10 @@
 use crate::{
-    node_allocator::{NodeAllocator, ZeroCopy, SENTINEL},
+    node_allocator::{Node, NodeAllocator, ZeroCopy, SENTINEL},
     FromSlice,
 };
+use alloc::vec::Vec;
 use bytemuck::{Pod, Zeroable};
+use core::ops::{Add, Deref};
 
 // Register aliases
 pub const PREV: u32 = 0;
@@ -35,7 +37,7 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> FromSlic
     for Deque<T, MAX_SIZE>
 {
     fn new_from_slice(slice: &mut [u8]) -> &mut Self {
-        let deque = Self::load_mut_bytes(slice).unwrap();
+        let deque = Self::try_load_mut_bytes(slice).unwrap();
         deque.initialize();
         deque
     }
@@ -63,6 +65,19 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deque<T,
         self.allocator.initialize();
     }
 
+    /// Initializes a deque in the zeroed buffer `buf` containing `items`, with `items[0]` at the
+    /// front.
+    ///
+    /// Panics if `items` does not fit in the deque.
+    pub fn fill_from_slice<'a>(buf: &'a mut [u8], items: &[T]) -> &'a mut Self {
+        assert!(items.len() <= MAX_SIZE, "Too many items for the deque");
+        let deque = Self::new_from_slice(buf);
+        for item in items {
+            deque.push_back(*item);
+        }
+        deque
+    }
+
     pub fn front(&self) -> Option<&T> {
         if self.head == SENTINEL {
             return None;
@@ -77,6 +92,70 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deque<T,
         Some(self.allocator.get(self.tail).get_value())
     }
 
+    /// Returns a mutable reference to the front element. Mutating an element in place is not a
+    /// structural change, so it does not bump the sequence number.
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        if self.head == SENTINEL {
+            return None;
+        }
+        Some(self.allocator.get_mut(self.head).get_value_mut())
+    }
+
+    /// Returns a mutable reference to the back element. Mutating an element in place is not a
+    /// structural change, so it does not bump the sequence number.
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        if self.tail == SENTINEL {
+            return None;
+        }
+        Some(self.allocator.get_mut(self.tail).get_value_mut())
+    }
+
+    /// Returns the element at logical position `index` from the front (0 is the front). This walks
+    /// the list, so it is O(index).
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let node = self.node_at(index)?;
+        Some(self.get_node(node))
+    }
+
+    /// Returns a mutable reference to the element at logical position `index` from the front.
+    /// Like `front_mut`, this does not bump the sequence number.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let node = self.node_at(index)?;
+        Some(self.allocator.get_mut(node).get_value_mut())
+    }
+
+    fn node_at(&self, index: usize) -> Option<u32> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = self.head;
+        for _ in 0..index {
+            node = self.get_next(node);
+        }
+        Some(node)
+    }
+
+    /// Returns a view of the deque that only allows queue (FIFO) operations. In debug builds,
+    /// calling `push_front` or `pop_back` through the view panics. The check lives in the view
+    /// rather than the deque so that the on-chain layout of `Deque` is unaffected.
+    pub fn strict_fifo(&mut self) -> StrictFifo<'_, T, MAX_SIZE> {
+        StrictFifo { deque: self }
+    }
+
+    /// Returns an iterator that removes the elements from the front (or from the back with
+    /// `next_back`). Dropping the iterator removes any elements that were not consumed, so the
+    /// deque is always empty afterwards.
+    pub fn drain(&mut self) -> Drain<'_, T, MAX_SIZE> {
+        Drain { deque: self }
+    }
+
+    /// Returns a monotonically increasing change token that is bumped on every push and pop.
+    /// Readers can detect concurrent structural changes by re-reading the deque and comparing
+    /// sequence numbers. In-place mutation of elements is not tracked.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
     pub fn get_next(&self, index: u32) -> u32 {
         self.allocator.get_register(index, NEXT)
     }
@@ -119,7 +198,7 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deque<T,
             return None;
         }
         let head = self.head;
-        self._remove(head)
+        self.remove_at(head)
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
@@ -127,18 +206,47 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deque<T,
             return None;
         }
         let tail = self.tail;
-        self._remove(tail)
+        self.remove_at(tail)
     }
 
-    fn _remove(&mut self, i: u32) -> Option<T> {
+    /// Inserts `value` at logical position `index` so that it ends up at `get(index)`, shifting
+    /// the elements after it towards the back. Returns the allocator index of the new node, or
+    /// `None` if the deque is full or `index > len()`.
+    pub fn insert(&mut self, index: usize, value: T) -> Option<usize> {
+        if index > self.len() || self.allocator.is_full() {
+            return None;
+        }
+        if index == 0 {
+            self.push_front(value);
+            return Some(self.head as usize);
+        }
+        if index == self.len() {
+            self.push_back(value);
+            return Some(self.tail as usize);
+        }
+        let right = self.node_at(index)?;
+        let left = self.get_prev(right);
+        let node = self.allocator.add_node(value);
+        self.allocator.connect(left, node, NEXT, PREV);
+        self.allocator.connect(node, right, NEXT, PREV);
+        self.sequence_number += 1;
+        Some(node as usize)
+    }
+
+    /// Removes the node at allocator index `i` in O(1), e.g. an index yielded by `iter`. `i` must
+    /// refer to an element currently in the deque.
+    pub fn remove_at(&mut self, i: u32) -> Option<T> {
+        if i == SENTINEL {
+            return None;
+        }
         let (left, right, value) = {
             let value = *self.get_node(i);
             let left = self.get_prev(i);
             let right = self.get_next(i);
             (left, right, value)
         };
-        self.allocator.clear_register(i as u32, PREV);
-        self.allocator.clear_register(i as u32, NEXT);
+        self.allocator.clear_register(i, PREV);
+        self.allocator.clear_register(i, NEXT);
         if left != SENTINEL && right != SENTINEL {
             self.allocator.connect(left, right, NEXT, PREV);
         }
@@ -150,19 +258,83 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deque<T,
             self.tail = left;
             self.allocator.clear_register(left, NEXT);
         }
-        self.allocator.remove_node(i as u32);
+        self.allocator.remove_node(i);
         self.sequence_number += 1;
         Some(value)
     }
 
+    /// Moves the first `n % len()` elements to the back, like `VecDeque::rotate_left`. The list is
+    /// closed into a ring by linking `tail` to `head` and then cut at the new head, so apart from
+    /// walking to the cut point (at most `len() / 2` steps, from whichever end is closer) only the
+    /// boundary registers are rewritten.
+    pub fn rotate_left(&mut self, n: usize) {
+        let len = self.len();
+        if len == 0 || n.is_multiple_of(len) {
+            return;
+        }
+        let n = n % len;
+        let new_head = if n <= len / 2 {
+            let mut node = self.head;
+            for _ in 0..n {
+                node = self.get_next(node);
+            }
+            node
+        } else {
+            let mut node = self.tail;
+            for _ in 0..len - n - 1 {
+                node = self.get_prev(node);
+            }
+            node
+        };
+        let new_tail = self.get_prev(new_head);
+        self.allocator.connect(self.tail, self.head, NEXT, PREV);
+        self.allocator.disconnect(new_tail, new_head, NEXT, PREV);
+        self.head = new_head;
+        self.tail = new_tail;
+        self.sequence_number += 1;
+    }
+
+    /// Moves every element of `other` to the back of `self`, leaving `other` empty. The deques
+    /// have separate allocators, so the nodes are moved one at a time in O(n) rather than
+    /// spliced. Returns false without modifying either deque if `self` does not have room for all
+    /// of `other`'s elements.
+    pub fn append(&mut self, other: &mut Self) -> bool {
+        if other.len() > self.remaining_capacity() {
+            return false;
+        }
+        while let Some(value) = other.pop_front() {
+            self.push_back(value);
+        }
+        true
+    }
+
+    /// Removes every element in place, leaving the deque as if it had just been initialized over
+    /// the same buffer. This counts as a structural change and bumps the sequence number.
+    pub fn clear(&mut self) {
+        self.head = SENTINEL;
+        self.tail = SENTINEL;
+        self.allocator.reset();
+        self.sequence_number += 1;
+    }
+
     pub fn len(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// Returns true if another element cannot be pushed
+    pub fn is_full(&self) -> bool {
+        self.allocator.is_full()
+    }
+
+    /// Returns the number of elements that can be pushed before the deque is full
+    pub fn remaining_capacity(&self) -> usize {
+        self.allocator.remaining_capacity()
+    }
+
     pub fn iter(&self) -> DequeIterator<'_, T, MAX_SIZE> {
         DequeIterator::<T, MAX_SIZE> {
             deque: self,
@@ -182,6 +354,193 @@ impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deque<T,
             terminated: false,
         }
     }
+
+    /// Returns the allocator nodes backing the deque if the elements occupy indices `1..=len()`
+    /// in order, e.g. when the deque has only ever been appended to with `push_back`. Returns
+    /// `None` otherwise.
+    ///
+    /// Each node stores its registers inline with the value, so the view is a slice of nodes
+    /// rather than a `&[T]`. The values can be read in order with `Node::get_value`.
+    pub fn as_contiguous_slice(&self) -> Option<&[Node<T, 2>]> {
+        let len = self.len();
+        if len == 0 {
+            return Some(&[]);
+        }
+        if self.head != 1 || self.tail != len as u32 {
+            return None;
+        }
+        for i in 1..len as u32 {
+            if self.get_next(i) != i + 1 {
+                return None;
+            }
+        }
+        Some(&self.allocator.nodes[..len])
+    }
+
+    /// Returns an iterator over consecutive groups of up to `n` elements, from front to back.
+    /// The last chunk holds the remainder if `len()` is not a multiple of `n`.
+    pub fn chunks(&self, n: usize) -> impl Iterator<Item = Vec<&T>> + '_ {
+        assert!(n > 0, "Chunk size must be non-zero");
+        let mut iter = self.iter();
+        core::iter::from_fn(move || {
+            let chunk = iter.by_ref().take(n).map(|(_, v)| v).collect::<Vec<_>>();
+            if chunk.is_empty() {
+                None
+            } else {
+                Some(chunk)
+            }
+        })
+    }
+
+    /// Folds the values of the deque from front to back.
+    pub fn fold<B, F: FnMut(B, &T) -> B>(&self, init: B, mut f: F) -> B {
+        self.iter().fold(init, |acc, (_, v)| f(acc, v))
+    }
+
+    /// Returns the sum of the values in the deque, starting from `T::default()`.
+    pub fn sum(&self) -> T
+    where
+        T: Add<Output = T>,
+    {
+        self.fold(T::default(), |acc, v| acc + *v)
+    }
+}
+
+impl<T: Default + Copy + Clone + Pod + Zeroable + PartialEq, const MAX_SIZE: usize>
+    Deque<T, MAX_SIZE>
+{
+    pub fn contains(&self, value: &T) -> bool {
+        self.position(value).is_some()
+    }
+
+    /// Returns the logical index (0 is the front) of the first element equal to `value`.
+    pub fn position(&self, value: &T) -> Option<usize> {
+        let mut node = self.head;
+        let mut index = 0;
+        while node != SENTINEL {
+            if self.get_node(node) == value {
+                return Some(index);
+            }
+            node = self.get_next(node);
+            index += 1;
+        }
+        None
+    }
+}
+
+/// Pushes each item to the back of the deque. Panics if the deque fills up before the iterator
+/// is exhausted.
+impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Extend<T>
+    for Deque<T, MAX_SIZE>
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            assert!(
+                !self.allocator.is_full(),
+                "Deque is full, capacity {}",
+                MAX_SIZE
+            );
+            self.push_back(item);
+        }
+    }
+}
+
+/// Collects into a deque built with `new`, with the first item at the front. Panics if there are
+/// more than `MAX_SIZE` items.
+impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> FromIterator<T>
+    for Deque<T, MAX_SIZE>
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut deque = Self::new();
+        deque.extend(iter);
+        deque
+    }
+}
+
+/// A `Deque` restricted to pushing at the back and popping from the front. Read-only access to
+/// the underlying deque is available through `Deref`.
+pub struct StrictFifo<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> {
+    deque: &'a mut Deque<T, MAX_SIZE>,
+}
+
+impl<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize>
+    StrictFifo<'a, T, MAX_SIZE>
+{
+    pub fn push_back(&mut self, node: T) {
+        self.deque.push_back(node)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.deque.pop_front()
+    }
+
+    /// Panics in debug builds. In release builds this behaves like `Deque::push_front`.
+    pub fn push_front(&mut self, node: T) {
+        if cfg!(debug_assertions) {
+            panic!("push_front called on a strict FIFO deque");
+        }
+        self.deque.push_front(node)
+    }
+
+    /// Panics in debug builds. In release builds this behaves like `Deque::pop_back`.
+    pub fn pop_back(&mut self) -> Option<T> {
+        if cfg!(debug_assertions) {
+            panic!("pop_back called on a strict FIFO deque");
+        }
+        self.deque.pop_back()
+    }
+}
+
+impl<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Deref
+    for StrictFifo<'a, T, MAX_SIZE>
+{
+    type Target = Deque<T, MAX_SIZE>;
+
+    fn deref(&self) -> &Self::Target {
+        self.deque
+    }
+}
+
+/// Draining iterator returned by `Deque::drain`
+pub struct Drain<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> {
+    deque: &'a mut Deque<T, MAX_SIZE>,
+}
+
+impl<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Iterator
+    for Drain<'a, T, MAX_SIZE>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.deque.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.deque.len(), Some(self.deque.len()))
+    }
+}
+
+impl<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> DoubleEndedIterator
+    for Drain<'a, T, MAX_SIZE>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.deque.pop_back()
+    }
+}
+
+impl<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> ExactSizeIterator
+    for Drain<'a, T, MAX_SIZE>
+{
+}
+
+impl<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Drop
+    for Drain<'a, T, MAX_SIZE>
+{
+    fn drop(&mut self) {
+        if !self.deque.is_empty() {
+            self.deque.clear();
+        }
+    }
 }
 
 pub struct DequeIterator<'a, T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> {
@@ -399,3 +758,442 @@ fn test_deque() {
     });
     assert!(q.is_empty() && v.is_empty());
 }
+
+#[test]
+fn test_deque_chunks() {
+    type Q = Deque<u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    assert!(q.chunks(3).next().is_none());
+    (0..10).for_each(|i| q.push_back(i));
+
+    let chunks = q
+        .chunks(3)
+        .map(|c| c.into_iter().copied().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        vec![vec![0, 1, 2], vec![3, 4, 5], vec![6, 7, 8], vec![9]]
+    );
+
+    // A chunk size larger than the deque yields a single chunk
+    let chunks = q.chunks(32).collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].len(), 10);
+}
+
+#[test]
+fn test_deque_as_contiguous_slice() {
+    type Q = Deque<u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    assert_eq!(q.as_contiguous_slice().map(|s| s.len()), Some(0));
+    (0..10).for_each(|i| q.push_back(i));
+
+    let values = q
+        .as_contiguous_slice()
+        .unwrap()
+        .iter()
+        .map(|n| *n.get_value())
+        .collect::<Vec<_>>();
+    assert_eq!(values, (0..10).collect::<Vec<_>>());
+
+    q.pop_front();
+    assert!(q.as_contiguous_slice().is_none());
+
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    q.push_back(1);
+    q.push_front(0);
+    assert!(q.as_contiguous_slice().is_none());
+}
+
+#[test]
+fn test_deque_fold_and_sum() {
+    use rand::Rng;
+    type Q = Deque<u64, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    assert_eq!(q.sum(), 0);
+    let mut rng = rand::thread_rng();
+    let mut total = 0;
+    for i in 0..200 {
+        let t = rng.gen_range(0, 1 << 32);
+        if i % 2 == 0 {
+            q.push_back(t);
+        } else {
+            q.push_front(t);
+        }
+        total += t;
+    }
+    assert_eq!(q.sum(), total);
+    assert_eq!(q.fold(0usize, |n, _| n + 1), q.len());
+    let front = *q.front().unwrap();
+    assert_eq!(q.fold(None, |first, v| first.or(Some(*v))), Some(front));
+}
+
+#[test]
+fn test_deque_sequence_number() {
+    type Q = Deque<u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    assert_eq!(q.sequence_number(), 0);
+    assert!(q.front_mut().is_none() && q.back_mut().is_none());
+    q.push_back(1);
+    q.push_front(0);
+    assert_eq!(q.sequence_number(), 2);
+
+    // Peeking, in-place mutation, and iteration are not structural changes
+    *q.front_mut().unwrap() += 10;
+    *q.back_mut().unwrap() += 20;
+    q.iter_mut().for_each(|(_, v)| *v += 1);
+    assert_eq!(q.front(), Some(&11));
+    assert_eq!(q.back(), Some(&22));
+    assert_eq!(q.sequence_number(), 2);
+
+    assert_eq!(q.pop_front(), Some(11));
+    assert_eq!(q.sequence_number(), 3);
+    assert_eq!(q.pop_back(), Some(22));
+    assert_eq!(q.sequence_number(), 4);
+    // Popping an empty deque changes nothing
+    assert!(q.pop_front().is_none() && q.pop_back().is_none());
+    assert_eq!(q.sequence_number(), 4);
+}
+
+#[test]
+fn test_deque_strict_fifo() {
+    type Q = Deque<u64, 8>;
+    // The view is just a reference, so it adds no state to the deque
+    assert_eq!(
+        std::mem::size_of::<StrictFifo<u64, 8>>(),
+        std::mem::size_of::<&mut Q>()
+    );
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    let mut fifo = q.strict_fifo();
+    for i in 0..4 {
+        fifo.push_back(i);
+    }
+    assert_eq!(fifo.front(), Some(&0));
+    assert_eq!(fifo.pop_front(), Some(0));
+    assert_eq!(fifo.len(), 3);
+    assert_eq!(q.iter().map(|(_, v)| *v).collect::<Vec<_>>(), vec![1, 2, 3]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "push_front called on a strict FIFO deque")]
+fn test_deque_strict_fifo_push_front_panics() {
+    type Q = Deque<u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    q.strict_fifo().push_front(1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "pop_back called on a strict FIFO deque")]
+fn test_deque_strict_fifo_pop_back_panics() {
+    type Q = Deque<u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Q>()];
+    let q = Q::new_from_slice(buf.as_mut_slice());
+    let mut fifo = q.strict_fifo();
+    fifo.push_back(1);
+    fifo.pop_back();
+}
+
+#[test]
+fn test_fill_from_slice() {
+    type D = Deque<u64, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<D>()];
+    let items = (0..16).map(|i| i * 3).collect::<Vec<u64>>();
+    let deque = D::fill_from_slice(buf.as_mut_slice(), &items);
+    assert_eq!(deque.len(), items.len());
+    assert_eq!(deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(), items);
+    assert_eq!(deque.front(), Some(&0));
+    assert_eq!(deque.back(), Some(&45));
+
+    let mut buf = vec![0u8; std::mem::size_of::<D>()];
+    let deque = D::fill_from_slice(buf.as_mut_slice(), &items[..3]);
+    assert_eq!(
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![0, 3, 6]
+    );
+    let mut buf = vec![0u8; std::mem::size_of::<D>()];
+    let deque = D::fill_from_slice(buf.as_mut_slice(), &[]);
+    assert!(deque.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Too many items for the deque")]
+fn test_fill_from_slice_too_many_items() {
+    type D = Deque<u64, 4>;
+    let mut buf = vec![0u8; std::mem::size_of::<D>()];
+    D::fill_from_slice(buf.as_mut_slice(), &[1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_get() {
+    let mut deque = Deque::<u64, 8>::new();
+    assert_eq!(deque.get(0), None);
+    for i in 0..5 {
+        deque.push_back(i * 10);
+    }
+    deque.push_front(100);
+    assert_eq!(deque.get(0), Some(&100));
+    assert_eq!(deque.get(1), Some(&0));
+    assert_eq!(deque.get(deque.len() - 1), Some(&40));
+    assert_eq!(deque.get(deque.len()), None);
+    assert_eq!(deque.get(usize::MAX), None);
+    for (i, (_, v)) in deque.iter().enumerate() {
+        assert_eq!(deque.get(i), Some(v));
+    }
+
+    let sequence_number = deque.sequence_number();
+    *deque.get_mut(2).unwrap() = 7;
+    assert_eq!(deque.get(2), Some(&7));
+    let last = deque.len() - 1;
+    *deque.get_mut(last).unwrap() += 1;
+    assert_eq!(deque.back(), Some(&41));
+    assert!(deque.get_mut(6).is_none());
+    assert_eq!(deque.sequence_number(), sequence_number);
+}
+
+#[test]
+fn test_insert_and_remove_at() {
+    let mut deque = Deque::<u64, 8>::new();
+    assert_eq!(deque.insert(1, 0), None);
+    let first = deque.insert(0, 2).unwrap();
+    assert_eq!(deque.head as usize, first);
+    assert_eq!(deque.tail as usize, first);
+    // front
+    deque.insert(0, 0).unwrap();
+    // end
+    let last = deque.insert(deque.len(), 4).unwrap();
+    assert_eq!(deque.tail as usize, last);
+    // middle
+    deque.insert(1, 1).unwrap();
+    let middle = deque.insert(3, 3).unwrap();
+    assert_eq!(deque.get(3), Some(&3));
+    assert_eq!(
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![0, 1, 2, 3, 4]
+    );
+    assert_eq!(
+        deque.iter().rev().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![4, 3, 2, 1, 0]
+    );
+    assert_eq!(deque.insert(6, 6), None);
+
+    assert_eq!(deque.remove_at(middle as u32), Some(3));
+    assert_eq!(deque.remove_at(SENTINEL), None);
+    let (head, _) = deque.iter().next().unwrap();
+    assert_eq!(deque.remove_at(head as u32), Some(0));
+    assert_eq!(deque.remove_at(last as u32), Some(4));
+    assert_eq!(
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert_eq!(deque.front(), Some(&1));
+    assert_eq!(deque.back(), Some(&2));
+
+    for i in 0..6 {
+        assert!(deque.insert(1, 10 + i).is_some());
+    }
+    assert_eq!(deque.len(), 8);
+    assert_eq!(deque.insert(1, 100), None);
+    assert_eq!(
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![1, 15, 14, 13, 12, 11, 10, 2]
+    );
+}
+
+#[test]
+fn test_contains_and_position() {
+    let mut deque = Deque::<u64, 8>::new();
+    assert!(!deque.contains(&0));
+    assert_eq!(deque.position(&0), None);
+    for v in [5, 3, 9, 3] {
+        deque.push_back(v);
+    }
+    deque.push_front(1);
+    assert!(deque.contains(&1));
+    assert!(deque.contains(&9));
+    assert!(!deque.contains(&4));
+    assert_eq!(deque.position(&1), Some(0));
+    assert_eq!(deque.position(&5), Some(1));
+    assert_eq!(deque.position(&3), Some(2));
+    assert_eq!(deque.position(&9), Some(3));
+    assert_eq!(deque.position(&4), None);
+    deque.pop_front();
+    assert_eq!(deque.position(&1), None);
+    assert_eq!(deque.position(&3), Some(1));
+}
+
+#[test]
+fn test_clear() {
+    type D = Deque<u64, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<D>()];
+    let deque = D::new_from_slice(buf.as_mut_slice());
+    for i in 0..16 {
+        deque.push_back(i);
+    }
+    deque.pop_front();
+    deque.pop_back();
+    let sequence_number = deque.sequence_number();
+    deque.clear();
+    assert!(deque.is_empty());
+    assert_eq!(deque.front(), None);
+    assert_eq!(deque.back(), None);
+    assert_eq!(deque.iter().count(), 0);
+    assert!(deque.sequence_number() > sequence_number);
+
+    // All MAX_SIZE slots are usable again after clearing
+    for i in 0..16 {
+        deque.push_front(i);
+    }
+    assert_eq!(deque.len(), 16);
+    assert_eq!(
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        (0..16).rev().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        deque.iter().rev().map(|(_, v)| *v).collect::<Vec<_>>(),
+        (0..16).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_rotate_left() {
+    use std::collections::VecDeque;
+    let mut deque = Deque::<u64, 8>::new();
+    deque.rotate_left(3);
+    assert!(deque.is_empty());
+    for len in 1..=8u64 {
+        for n in 0..20 {
+            let mut deque = Deque::<u64, 8>::new();
+            let mut expected = VecDeque::new();
+            for i in 0..len {
+                deque.push_back(i);
+                expected.push_back(i);
+            }
+            deque.rotate_left(n);
+            expected.rotate_left(n % len as usize);
+            assert_eq!(
+                deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+                expected.iter().copied().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                deque.iter().rev().map(|(_, v)| *v).collect::<Vec<_>>(),
+                expected.iter().rev().copied().collect::<Vec<_>>()
+            );
+            assert_eq!(deque.front(), expected.front());
+            assert_eq!(deque.back(), expected.back());
+
+            // the rotated deque is still a well formed list
+            deque.pop_front();
+            expected.pop_front();
+            deque.push_back(100);
+            expected.push_back(100);
+            assert_eq!(
+                deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+                expected.iter().copied().collect::<Vec<_>>()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_extend_and_from_iter() {
+    let mut deque = (0..4u64).collect::<Deque<u64, 16>>();
+    assert_eq!(
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![0, 1, 2, 3]
+    );
+    deque.extend(vec![4, 5]);
+    deque.extend(std::iter::empty());
+    deque.push_front(100);
+    deque.extend((6..).take(9));
+    assert_eq!(deque.len(), 16);
+    assert_eq!(deque.front(), Some(&100));
+    assert_eq!(deque.back(), Some(&14));
+
+    // A zero-copy deque can be extended in place
+    type D = Deque<u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<D>()];
+    let deque = D::new_from_slice(buf.as_mut_slice());
+    deque.extend(0..8);
+    assert_eq!(deque.sum(), 28);
+}
+
+#[test]
+#[should_panic(expected = "Deque is full, capacity 4")]
+fn test_extend_overflow() {
+    let mut deque = Deque::<u64, 4>::new();
+    deque.extend(0..3);
+    deque.extend(3..5);
+}
+
+#[test]
+#[should_panic(expected = "Deque is full, capacity 4")]
+fn test_from_iter_overflow() {
+    let _ = (0..5u64).collect::<Deque<u64, 4>>();
+}
+
+#[test]
+fn test_append() {
+    let mut a = Deque::<u64, 16>::new();
+    let mut b = Deque::<u64, 16>::new();
+    a.extend(0..6);
+    b.extend(6..16);
+    assert!(a.append(&mut b));
+    assert!(b.is_empty());
+    assert_eq!(
+        a.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        (0..16).collect::<Vec<_>>()
+    );
+    // `b` can be reused after being drained
+    b.extend(16..18);
+    assert!(!a.append(&mut b));
+    assert_eq!(a.len(), 16);
+    assert!(a.is_full());
+    assert_eq!(a.remaining_capacity(), 0);
+    assert_eq!(b.len(), 2);
+    assert!(!b.is_full());
+    assert_eq!(b.remaining_capacity(), 14);
+    a.pop_front();
+    a.pop_front();
+    assert_eq!(a.remaining_capacity(), 2);
+    assert!(a.append(&mut b));
+    assert_eq!(
+        a.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        (2..18).collect::<Vec<_>>()
+    );
+    assert!(a.append(&mut b));
+}
+
+#[test]
+fn test_drain() {
+    let mut deque = (0..16).collect::<Deque<u64, 16>>();
+    let mut drain = deque.drain();
+    assert_eq!(drain.len(), 16);
+    assert_eq!(drain.next(), Some(0));
+    assert_eq!(drain.next_back(), Some(15));
+    assert_eq!(drain.next(), Some(1));
+    assert_eq!(drain.len(), 13);
+    // Dropping the partially consumed iterator removes the rest
+    drop(drain);
+    assert!(deque.is_empty());
+    assert_eq!(deque.iter().count(), 0);
+
+    deque.extend(0..16);
+    assert_eq!(
+        deque.drain().rev().collect::<Vec<_>>(),
+        (0..16).rev().collect::<Vec<_>>()
+    );
+    assert!(deque.is_empty());
+    // The freed slots can be reused
+    deque.extend(16..32);
+    assert_eq!(deque.len(), 16);
+    assert_eq!(deque.front(), Some(&16));
+}
@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Error)]
+pub enum SokobanError {
+    #[error("The container does not have enough free capacity")]
+    Full,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Error)]
+pub enum ZeroCopyError {
+    #[error("The slice is too short: {needed} bytes are needed but only {got} were provided")]
+    SliceTooShort { needed: usize, got: usize },
+    #[error("The slice is not aligned to the alignment of the type")]
+    Misaligned,
+}
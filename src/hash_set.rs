@@ -0,0 +1,235 @@
+use crate::hash_table::DefaultBuildHasher;
+use crate::hash_table::HashTable;
+use crate::node_allocator::{FromSlice, NodeAllocatorMap, ZeroCopy};
+use bytemuck::{Pod, Zeroable};
+use core::hash::{BuildHasher, Hash};
+
+/// Hash set backed by a `HashTable` with `()` values, so it has the same layout and bucket
+/// behavior as the table. The set operations borrow both sets and stream their results without
+/// allocating.
+#[repr(C)]
+pub struct HashSet<
+    K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+    const NUM_BUCKETS: usize,
+    const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static = DefaultBuildHasher,
+> {
+    table: HashTable<K, (), NUM_BUCKETS, MAX_SIZE, S>,
+}
+
+// Implemented manually since the derives would require `S: Copy`
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Clone for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Copy for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+}
+
+unsafe impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Zeroable for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+}
+unsafe impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Pod for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > ZeroCopy for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > FromSlice for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+    fn new_from_slice(slice: &mut [u8]) -> &mut Self {
+        let set = Self::try_load_mut_bytes(slice).unwrap();
+        set.initialize();
+        set
+    }
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Default for HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+    fn default() -> Self {
+        Self {
+            table: HashTable::default(),
+        }
+    }
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > HashSet<K, NUM_BUCKETS, MAX_SIZE, S>
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn initialize(&mut self) {
+        self.table.initialize();
+    }
+
+    /// Adds `key` to the set. Like `HashTable::insert`, this returns the address of the key's
+    /// node (whether or not it was already present), or `None` if the set is full.
+    pub fn insert(&mut self, key: K) -> Option<u32> {
+        self.table.insert(key, ())
+    }
+
+    /// Removes `key` from the set, returning whether it was present
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.table.remove(key).is_some()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.table.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        MAX_SIZE
+    }
+
+    pub fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    /// Returns the keys in bucket order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &K> + '_ {
+        (&self.table).into_iter().map(|(k, _)| k)
+    }
+
+    /// Returns the keys in `self` followed by the keys of `other` that are not in `self`
+    pub fn union<'a, const B: usize, const M: usize, S2: BuildHasher + Default + 'static>(
+        &'a self,
+        other: &'a HashSet<K, B, M, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().chain(other.difference(self))
+    }
+
+    /// Returns the keys in `self` that are also in `other`
+    pub fn intersection<'a, const B: usize, const M: usize, S2: BuildHasher + Default + 'static>(
+        &'a self,
+        other: &'a HashSet<K, B, M, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().filter(move |k| other.contains(k))
+    }
+
+    /// Returns the keys in `self` that are not in `other`
+    pub fn difference<'a, const B: usize, const M: usize, S2: BuildHasher + Default + 'static>(
+        &'a self,
+        other: &'a HashSet<K, B, M, S2>,
+    ) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().filter(move |k| !other.contains(k))
+    }
+}
+
+#[test]
+fn test_hash_set() {
+    type Set = HashSet<u64, 8, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Set>()];
+    let set = Set::new_from_slice(buf.as_mut_slice());
+    assert!(set.is_empty());
+    for k in 0..16 {
+        assert!(set.insert(k * 3).is_some());
+    }
+    assert!(set.insert(0).is_some());
+    assert!(set.insert(100).is_none());
+    assert_eq!(set.len(), 16);
+    assert!(set.contains(&9));
+    assert!(!set.contains(&10));
+    assert!(set.remove(&9));
+    assert!(!set.remove(&9));
+    assert!(!set.contains(&9));
+    assert_eq!(set.iter().count(), 15);
+    assert_eq!(set.iter().rev().count(), 15);
+    set.clear();
+    assert!(set.is_empty());
+    assert!(!set.contains(&0));
+}
+
+#[test]
+fn test_set_operations_match_std() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    for _ in 0..32 {
+        let mut a = HashSet::<u64, 16, 64>::new();
+        let mut b = HashSet::<u64, 32, 128>::new();
+        let mut std_a = std::collections::HashSet::new();
+        let mut std_b = std::collections::HashSet::new();
+        for _ in 0..rng.gen_range(0, 64) {
+            let k = rng.gen_range(0, 128);
+            a.insert(k);
+            std_a.insert(k);
+        }
+        for _ in 0..rng.gen_range(0, 128) {
+            let k = rng.gen_range(0, 128);
+            b.insert(k);
+            std_b.insert(k);
+        }
+        let sorted = |iter: &mut dyn Iterator<Item = &u64>| {
+            let mut keys = iter.copied().collect::<Vec<_>>();
+            keys.sort_unstable();
+            keys
+        };
+        assert_eq!(sorted(&mut a.union(&b)), sorted(&mut std_a.union(&std_b)));
+        assert_eq!(sorted(&mut b.union(&a)), sorted(&mut std_b.union(&std_a)));
+        assert_eq!(
+            sorted(&mut a.intersection(&b)),
+            sorted(&mut std_a.intersection(&std_b))
+        );
+        assert_eq!(
+            sorted(&mut a.difference(&b)),
+            sorted(&mut std_a.difference(&std_b))
+        );
+        assert_eq!(
+            sorted(&mut b.difference(&a)),
+            sorted(&mut std_b.difference(&std_a))
+        );
+    }
+}
@@ -1,11 +1,11 @@
 use crate::node_allocator::{
     FromSlice, NodeAllocator, NodeAllocatorMap, NodeField, ZeroCopy, SENTINEL,
 };
+use alloc::{boxed::Box, vec, vec::Vec};
 use bytemuck::{Pod, Zeroable};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hasher;
-use std::{
-    hash::Hash,
+use core::{
+    hash::{BuildHasher, BuildHasherDefault, Hash, Hasher},
+    marker::PhantomData,
     ops::{Index, IndexMut},
 };
 
@@ -42,16 +42,164 @@ impl<
     }
 }
 
-#[repr(C)]
+/// The hasher used by `HashTable` and `HashSet` when none is specified. It is the same with and
+/// without the `std` feature, so enabling `std` anywhere in a dependency graph never changes which
+/// bucket a key is stored in.
+pub type DefaultBuildHasher = BuildHasherDefault<SipHasher13>;
+
+/// SipHash-1-3, zero-keyed when built with `default`. Earlier versions of this crate defaulted to
+/// `std`'s `DefaultHasher::new()`, which was zero-keyed SipHash-1-3 at the time. Defining the
+/// algorithm here keeps the bucket layout of those tables stable in every build, independently of
+/// the hasher `std` happens to use.
 #[derive(Copy, Clone)]
+pub struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    /// Unprocessed trailing bytes, little-endian
+    tail: u64,
+    /// Number of valid bytes in `tail`
+    ntail: usize,
+    /// Total number of bytes written
+    length: usize,
+}
+
+impl Default for SipHasher13 {
+    fn default() -> Self {
+        Self::new_with_keys(0, 0)
+    }
+}
+
+impl SipHasher13 {
+    /// Returns a SipHash-1-3 hasher keyed with the 128-bit key `k0 || k1`
+    pub fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn sip_round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    #[inline(always)]
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        self.sip_round();
+        self.v0 ^= m;
+    }
+}
+
+/// Reads up to 8 bytes as a little-endian integer
+#[inline(always)]
+fn load_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, byte)| acc | (*byte as u64) << (8 * i))
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len();
+        if self.ntail != 0 {
+            let fill = (8 - self.ntail).min(bytes.len());
+            self.tail |= load_le(&bytes[..fill]) << (8 * self.ntail);
+            self.ntail += fill;
+            bytes = &bytes[fill..];
+            if self.ntail < 8 {
+                return;
+            }
+            let m = self.tail;
+            self.compress(m);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+        let mut words = bytes.chunks_exact(8);
+        for word in words.by_ref() {
+            self.compress(load_le(word));
+        }
+        let rest = words.remainder();
+        self.tail = load_le(rest);
+        self.ntail = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = *self;
+        let b = ((self.length as u64 & 0xff) << 56) | self.tail;
+        state.compress(b);
+        state.v2 ^= 0xff;
+        for _ in 0..3 {
+            state.sip_round();
+        }
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+/// Hash table with separate chaining, where each bucket is a doubly linked list of nodes.
+///
+/// Every mutable access to an existing key (`get_mut`, `get_or_insert` and an overwriting
+/// `insert`) moves the key's node to the head of its bucket, so recently written keys are found
+/// first. New keys are also inserted at the head. Shared lookups with `get` never reorder a
+/// bucket.
+///
+/// Keys are hashed with a hasher built by `S::default()`, which must build the same hasher every
+/// time (e.g. `BuildHasherDefault<H>`, but not `RandomState`). Creating a table panics if `S` is
+/// not zero-sized, since only stateless builders are guaranteed to be deterministic.
+#[repr(C)]
 pub struct HashTable<
     K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
     V: Default + Copy + Clone + Pod + Zeroable,
     const NUM_BUCKETS: usize,
     const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static = DefaultBuildHasher,
 > {
     pub buckets: [u32; NUM_BUCKETS],
     pub allocator: NodeAllocator<HashNode<K, V>, MAX_SIZE, 4>,
+    /// The hasher builder is zero-sized and recreated with `S::default()` for every hash, so the
+    /// table stays `Pod`
+    _hasher: PhantomData<S>,
+}
+
+// Implemented manually since the derives would require `S: Copy`
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Clone for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Copy for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
 }
 
 unsafe impl<
@@ -59,7 +207,8 @@ unsafe impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > Zeroable for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > Zeroable for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
 }
 unsafe impl<
@@ -67,7 +216,8 @@ unsafe impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > Pod for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > Pod for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
 }
 
@@ -76,7 +226,8 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > ZeroCopy for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > ZeroCopy for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
 }
 
@@ -85,13 +236,15 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > Default for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > Default for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn default() -> Self {
         Self::assert_proper_alignment();
         HashTable {
             buckets: [SENTINEL; NUM_BUCKETS],
             allocator: NodeAllocator::<HashNode<K, V>, MAX_SIZE, 4>::default(),
+            _hasher: PhantomData,
         }
     }
 }
@@ -101,7 +254,8 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > NodeAllocatorMap<K, V> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > NodeAllocatorMap<K, V> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn insert(&mut self, key: K, value: V) -> Option<u32> {
         self._insert(key, value)
@@ -116,9 +270,7 @@ impl<
     }
 
     fn get(&self, key: &K) -> Option<&V> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let bucket_index = hasher.finish() as usize % NUM_BUCKETS;
+        let bucket_index = self.bucket_index(key);
         let mut curr_node = self.buckets[bucket_index];
         while curr_node != SENTINEL {
             let node = self.get_node(curr_node);
@@ -132,34 +284,13 @@ impl<
     }
 
     fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let bucket_index = hasher.finish() as usize % NUM_BUCKETS;
-        let head = self.buckets[bucket_index];
-        let mut curr_node = head;
+        let bucket_index = self.bucket_index(key);
+        let mut curr_node = self.buckets[bucket_index];
         while curr_node != SENTINEL {
             let node = self.get_node(curr_node);
             if node.key == *key {
                 // If get_mut is called, we move the matched node to the front of the queue
-                let prev = self.get_prev(curr_node);
-                let next = self.get_next(curr_node);
-                if curr_node != head {
-                    self.allocator
-                        .clear_register(curr_node, NodeField::Left as u32);
-                    self.allocator.connect(
-                        prev,
-                        next,
-                        NodeField::Right as u32,
-                        NodeField::Left as u32,
-                    );
-                    self.allocator.connect(
-                        curr_node,
-                        head,
-                        NodeField::Right as u32,
-                        NodeField::Left as u32,
-                    );
-                }
-                self.buckets[bucket_index] = curr_node;
+                self._move_to_front(bucket_index, curr_node);
                 return Some(&mut self.get_node_mut(curr_node).value);
             } else {
                 curr_node = self.get_next(curr_node);
@@ -169,17 +300,22 @@ impl<
     }
 
     fn size(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     fn len(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     fn capacity(&self) -> usize {
         MAX_SIZE
     }
 
+    fn clear(&mut self) {
+        self.buckets = [SENTINEL; NUM_BUCKETS];
+        self.allocator.reset();
+    }
+
     fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&K, &V)> + '_> {
         Box::new(self._iter())
     }
@@ -194,11 +330,12 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > FromSlice for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > FromSlice for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn new_from_slice(slice: &mut [u8]) -> &mut Self {
         Self::assert_proper_alignment();
-        let tab = Self::load_mut_bytes(slice).unwrap();
+        let tab = Self::try_load_mut_bytes(slice).unwrap();
         tab.initialize();
         tab
     }
@@ -209,10 +346,15 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn assert_proper_alignment() {
-        assert!(NUM_BUCKETS % 2 == 0);
+        assert!(NUM_BUCKETS.is_multiple_of(2));
+        assert!(
+            core::mem::size_of::<S>() == 0,
+            "HashTable: S must be a zero-sized, deterministic hasher builder"
+        );
     }
 
     pub fn initialize(&mut self) {
@@ -223,6 +365,12 @@ impl<
         Self::default()
     }
 
+    /// Returns the bucket that `key` hashes into
+    #[inline(always)]
+    fn bucket_index(&self, key: &K) -> usize {
+        S::default().hash_one(key) as usize % NUM_BUCKETS
+    }
+
     pub fn get_next(&self, index: u32) -> u32 {
         self.allocator.get_register(index, NodeField::Right as u32)
     }
@@ -239,25 +387,68 @@ impl<
         self.allocator.get_mut(index).get_value_mut()
     }
 
+    /// Returns the value for `key`, inserting `value` if the key is missing. The value of an
+    /// existing key is not overwritten. Returns `None` (without modifying the table) if the key is
+    /// missing and the table is full.
+    pub fn get_or_insert(&mut self, key: K, value: V) -> Option<&mut V> {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Like `get_or_insert`, but the value of a missing key is only computed when it is inserted.
+    /// The key is hashed once for both the lookup and the insert.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<&mut V> {
+        let (node_index, _) = self._get_or_insert_with(key, f)?;
+        Some(&mut self.get_node_mut(node_index).value)
+    }
+
+    /// Inserts `value` only if `key` is missing, returning whether it was inserted. Returns false
+    /// if the key is present (its value is kept, but like `get_or_insert` the node is moved to the
+    /// head of its bucket) or if the table is full.
+    pub fn insert_if_absent(&mut self, key: K, value: V) -> bool {
+        matches!(self._get_or_insert_with(key, || value), Some((_, true)))
+    }
+
     fn _insert(&mut self, key: K, value: V) -> Option<u32> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let bucket_index = hasher.finish() as usize % NUM_BUCKETS;
-        let head = self.buckets[bucket_index];
-        let mut curr_node = head;
-        while curr_node != SENTINEL {
-            let node = self.get_node(curr_node);
-            if node.key == key {
-                self.get_node_mut(curr_node).value = value;
-                return Some(curr_node);
-            } else {
-                curr_node = self.get_next(curr_node);
-            }
+        let (node_index, inserted) = self._get_or_insert_with(key, || value)?;
+        if !inserted {
+            self.get_node_mut(node_index).value = value;
+        }
+        Some(node_index)
+    }
+
+    /// Returns the index of the node matching `key`, or creates a node at the head of its bucket
+    /// with the value produced by `f`. The boolean flag indicates whether a new node was
+    /// inserted. The capacity check happens before the bucket is modified, so a failed insert
+    /// leaves the table untouched.
+    fn _get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<(u32, bool)> {
+        let bucket_index = self.bucket_index(&key);
+        let node_index = self._find_in_bucket(bucket_index, &key);
+        if node_index != SENTINEL {
+            // The caller gets mutable access to the value, so the node is promoted as in
+            // get_mut
+            self._move_to_front(bucket_index, node_index);
+            return Some((node_index, false));
+        }
+        Some((self._push_front(bucket_index, key, f)?, true))
+    }
+
+    /// Returns the node in the chain of `bucket_index` matching `key`, or `SENTINEL`
+    fn _find_in_bucket(&self, bucket_index: usize, key: &K) -> u32 {
+        let mut curr_node = self.buckets[bucket_index];
+        while curr_node != SENTINEL && self.get_node(curr_node).key != *key {
+            curr_node = self.get_next(curr_node);
         }
-        if self.len() >= self.capacity() {
+        curr_node
+    }
+
+    /// Links a new node for `key` as the head of `bucket_index`, with the value produced by `f`.
+    /// Returns `None` without calling `f` if the table is full.
+    fn _push_front<F: FnOnce() -> V>(&mut self, bucket_index: usize, key: K, f: F) -> Option<u32> {
+        if self.allocator.is_full() {
             return None;
         }
-        let node_index = self.allocator.add_node(HashNode::new(key, value));
+        let head = self.buckets[bucket_index];
+        let node_index = self.allocator.add_node(HashNode::new(key, f()));
         self.buckets[bucket_index] = node_index;
         if head != SENTINEL {
             self.allocator.connect(
@@ -270,30 +461,55 @@ impl<
         Some(node_index)
     }
 
-    pub fn _remove(&mut self, key: &K) -> Option<V> {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let bucket_index = hasher.finish() as usize % NUM_BUCKETS;
+    /// Returns the entry for `key`. The key is hashed once, and the bucket is reused if the entry
+    /// is inserted. An existing node is moved to the head of its bucket, as in `get_mut`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, NUM_BUCKETS, MAX_SIZE, S> {
+        let bucket_index = self.bucket_index(&key);
+        let node_index = self._find_in_bucket(bucket_index, &key);
+        if node_index == SENTINEL {
+            Entry::Vacant(VacantEntry {
+                table: self,
+                bucket_index,
+                key,
+            })
+        } else {
+            self._move_to_front(bucket_index, node_index);
+            Entry::Occupied(OccupiedEntry {
+                table: self,
+                bucket_index,
+                node_index,
+            })
+        }
+    }
+
+    /// Unlinks `node_index` from its position in the bucket and relinks it as the head
+    fn _move_to_front(&mut self, bucket_index: usize, node_index: u32) {
         let head = self.buckets[bucket_index];
+        if node_index == head {
+            return;
+        }
+        let prev = self.get_prev(node_index);
+        let next = self.get_next(node_index);
+        self.allocator
+            .clear_register(node_index, NodeField::Left as u32);
+        self.allocator
+            .connect(prev, next, NodeField::Right as u32, NodeField::Left as u32);
+        self.allocator.connect(
+            node_index,
+            head,
+            NodeField::Right as u32,
+            NodeField::Left as u32,
+        );
+        self.buckets[bucket_index] = node_index;
+    }
+
+    pub fn _remove(&mut self, key: &K) -> Option<V> {
+        let bucket_index = self.bucket_index(key);
         let mut curr_node = self.buckets[bucket_index];
         while curr_node != SENTINEL {
             let node = self.get_node(curr_node);
             if node.key == *key {
-                let val = node.value;
-                let prev = self.get_prev(curr_node);
-                let next = self.get_next(curr_node);
-                self.allocator
-                    .clear_register(curr_node, NodeField::Left as u32);
-                self.allocator
-                    .clear_register(curr_node, NodeField::Right as u32);
-                self.allocator.remove_node(curr_node);
-                if head == curr_node {
-                    assert!(prev == SENTINEL);
-                    self.buckets[bucket_index] = next;
-                }
-                self.allocator
-                    .connect(prev, next, NodeField::Right as u32, NodeField::Left as u32);
-                return Some(val);
+                return Some(self._unlink(bucket_index, curr_node));
             } else {
                 curr_node = self.get_next(curr_node);
             }
@@ -301,10 +517,78 @@ impl<
         None
     }
 
+    /// Removes the entry at allocator index `index` (e.g. an index yielded by `iter_indexed`),
+    /// returning its value. The bucket chain is searched for `index` itself rather than for its
+    /// key, and `None` is returned if `index` is not a live entry: a handle whose entry was
+    /// already removed, a slot that was never used, `SENTINEL` or an index past `MAX_SIZE`.
+    pub fn remove_at(&mut self, index: u32) -> Option<V> {
+        let key = self.allocator.try_get(index)?.get_value().key;
+        let bucket_index = self.bucket_index(&key);
+        let mut curr_node = self.buckets[bucket_index];
+        while curr_node != SENTINEL {
+            if curr_node == index {
+                return Some(self._unlink(bucket_index, index));
+            }
+            curr_node = self.get_next(curr_node);
+        }
+        None
+    }
+
+    /// Removes `node_index` from the chain of `bucket_index` and frees it, returning its value
+    fn _unlink(&mut self, bucket_index: usize, node_index: u32) -> V {
+        let val = self.get_node(node_index).value;
+        let prev = self.get_prev(node_index);
+        let next = self.get_next(node_index);
+        self.allocator
+            .clear_register(node_index, NodeField::Left as u32);
+        self.allocator
+            .clear_register(node_index, NodeField::Right as u32);
+        self.allocator.remove_node(node_index);
+        if self.buckets[bucket_index] == node_index {
+            assert!(prev == SENTINEL);
+            self.buckets[bucket_index] = next;
+        }
+        self.allocator
+            .connect(prev, next, NodeField::Right as u32, NodeField::Left as u32);
+        val
+    }
+
+    /// Returns the entries in the same bucket order as `iter`, along with the allocator index of
+    /// each entry's node. The index stays valid until the entry is removed and can be passed to
+    /// `get_node` or `remove_at`.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (u32, &K, &V)> + '_ {
+        self.buckets.iter().flat_map(move |&head| {
+            let mut curr_node = head;
+            core::iter::from_fn(move || {
+                if curr_node == SENTINEL {
+                    return None;
+                }
+                let node_index = curr_node;
+                let node = self.get_node(node_index);
+                curr_node = self.get_next(node_index);
+                Some((node_index, &node.key, &node.value))
+            })
+        })
+    }
+
+    /// Removes every entry for which `f` returns false. The chains are relinked in place, so the
+    /// surviving entries keep their relative order within each bucket.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        for bucket_index in 0..NUM_BUCKETS {
+            let mut curr_node = self.buckets[bucket_index];
+            while curr_node != SENTINEL {
+                let next = self.get_next(curr_node);
+                let node = self.get_node_mut(curr_node);
+                if !f(&node.key, &mut node.value) {
+                    self._unlink(bucket_index, curr_node);
+                }
+                curr_node = next;
+            }
+        }
+    }
+
     pub fn contains(&self, key: &K) -> bool {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let bucket_index = hasher.finish() as usize % NUM_BUCKETS;
+        let bucket_index = self.bucket_index(key);
         let mut curr_node = self.buckets[bucket_index];
         while curr_node != SENTINEL {
             let node = self.get_node(curr_node);
@@ -318,9 +602,7 @@ impl<
     }
 
     pub fn get_addr(&self, key: &K) -> u32 {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let bucket_index = hasher.finish() as usize % NUM_BUCKETS;
+        let bucket_index = self.bucket_index(key);
         let mut curr_node = self.buckets[bucket_index];
         while curr_node != SENTINEL {
             let node = self.get_node(curr_node);
@@ -333,22 +615,104 @@ impl<
         SENTINEL
     }
 
-    fn _iter(&self) -> HashTableIterator<'_, K, V, NUM_BUCKETS, MAX_SIZE> {
-        HashTableIterator::<K, V, NUM_BUCKETS, MAX_SIZE> {
+    /// Average number of entries per bucket
+    pub fn load_factor(&self) -> f64 {
+        self.len() as f64 / NUM_BUCKETS as f64
+    }
+
+    /// Number of entries in the longest bucket
+    pub fn max_chain_len(&self) -> usize {
+        (0..NUM_BUCKETS)
+            .map(|bucket_index| self.chain_len(bucket_index))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns a histogram of bucket lengths, where the `i`-th element is the number of buckets
+    /// that hold exactly `i` entries. The histogram has `max_chain_len() + 1` elements.
+    pub fn chain_len_histogram(&self) -> Vec<usize> {
+        let mut histogram = vec![];
+        for bucket_index in 0..NUM_BUCKETS {
+            let len = self.chain_len(bucket_index);
+            if histogram.len() <= len {
+                histogram.resize(len + 1, 0);
+            }
+            histogram[len] += 1;
+        }
+        histogram
+    }
+
+    fn chain_len(&self, bucket_index: usize) -> usize {
+        let mut len = 0;
+        let mut curr_node = self.buckets[bucket_index];
+        while curr_node != SENTINEL {
+            len += 1;
+            curr_node = self.get_next(curr_node);
+        }
+        len
+    }
+
+    /// Returns true when the load factor exceeds `threshold`.
+    ///
+    /// The bucket count and capacity are const generics, so a `HashTable` can never grow in
+    /// place. Callers that need more room should allocate a larger table and migrate into it
+    /// with `rehash_into` once `should_grow` returns true.
+    pub fn should_grow(&self, threshold: f64) -> bool {
+        self.load_factor() > threshold
+    }
+
+    /// Inserts every entry of `self` into `other`, which may have a different number of buckets
+    /// and capacity. Returns `None` without modifying `other` if it does not have room for all
+    /// of the entries.
+    pub fn rehash_into<
+        const OTHER_BUCKETS: usize,
+        const OTHER_SIZE: usize,
+        S2: BuildHasher + Default + 'static,
+    >(
+        &self,
+        other: &mut HashTable<K, V, OTHER_BUCKETS, OTHER_SIZE, S2>,
+    ) -> Option<()> {
+        if other.capacity() - other.len() < self.len() {
+            return None;
+        }
+        for (k, v) in self._iter() {
+            other.insert(*k, *v)?;
+        }
+        Some(())
+    }
+
+    fn _iter(&self) -> HashTableIterator<'_, K, V, NUM_BUCKETS, MAX_SIZE, S> {
+        HashTableIterator::<K, V, NUM_BUCKETS, MAX_SIZE, S> {
             ht: self,
             bucket: 0,
             node: self.buckets[0],
+            rev_bucket: NUM_BUCKETS,
+            rev_node: SENTINEL,
+            remaining: self.len(),
         }
     }
 
-    fn _iter_mut(&mut self) -> HashTableIteratorMut<'_, K, V, NUM_BUCKETS, MAX_SIZE> {
+    fn _iter_mut(&mut self) -> HashTableIteratorMut<'_, K, V, NUM_BUCKETS, MAX_SIZE, S> {
         let node = self.buckets[0];
-        HashTableIteratorMut::<K, V, NUM_BUCKETS, MAX_SIZE> {
+        let remaining = self.len();
+        HashTableIteratorMut::<K, V, NUM_BUCKETS, MAX_SIZE, S> {
             ht: self,
             bucket: 0,
             node,
+            rev_bucket: NUM_BUCKETS,
+            rev_node: SENTINEL,
+            remaining,
         }
     }
+
+    /// Returns the last node in the chain of `bucket`
+    fn bucket_tail(&self, bucket: usize) -> u32 {
+        let mut node = self.buckets[bucket];
+        while self.get_next(node) != SENTINEL {
+            node = self.get_next(node);
+        }
+        node
+    }
 }
 
 impl<
@@ -357,10 +721,11 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > IntoIterator for &'a HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > IntoIterator for &'a HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     type Item = (&'a K, &'a V);
-    type IntoIter = HashTableIterator<'a, K, V, NUM_BUCKETS, MAX_SIZE>;
+    type IntoIter = HashTableIterator<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>;
 
     fn into_iter(self) -> Self::IntoIter {
         self._iter()
@@ -373,26 +738,176 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > IntoIterator for &'a mut HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > IntoIterator for &'a mut HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     type Item = (&'a K, &'a mut V);
-    type IntoIter = HashTableIteratorMut<'a, K, V, NUM_BUCKETS, MAX_SIZE>;
+    type IntoIter = HashTableIteratorMut<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>;
 
     fn into_iter(self) -> Self::IntoIter {
         self._iter_mut()
     }
 }
 
+/// A view into a single key of a `HashTable`, which is either occupied or vacant.
+pub enum Entry<
+    'a,
+    K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const NUM_BUCKETS: usize,
+    const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static,
+> {
+    Occupied(OccupiedEntry<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>),
+    Vacant(VacantEntry<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>),
+}
+
+pub struct OccupiedEntry<
+    'a,
+    K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const NUM_BUCKETS: usize,
+    const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static,
+> {
+    table: &'a mut HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>,
+    bucket_index: usize,
+    node_index: u32,
+}
+
+pub struct VacantEntry<
+    'a,
+    K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const NUM_BUCKETS: usize,
+    const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static,
+> {
+    table: &'a mut HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>,
+    bucket_index: usize,
+    key: K,
+}
+
+impl<
+        'a,
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Entry<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value for the key, inserting `default` if the key is missing. Returns `None`
+    /// if the key is missing and the table is full.
+    pub fn or_insert(self, default: V) -> Option<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value for the key, inserting the result of `f` if the key is missing.
+    /// Returns `None` if the key is missing and the table is full.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert_with(f),
+        }
+    }
+
+    pub fn or_default(self) -> Option<&'a mut V> {
+        self.or_insert_with(V::default)
+    }
+
+    /// Applies `f` to the value if the key is present.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<
+        'a,
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > OccupiedEntry<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    pub fn key(&self) -> &K {
+        &self.table.get_node(self.node_index).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.table.get_node(self.node_index).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.table.get_node_mut(self.node_index).value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.table.get_node_mut(self.node_index).value
+    }
+
+    /// Replaces the value of the entry, returning the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the table, returning its value.
+    pub fn remove(self) -> V {
+        self.table._unlink(self.bucket_index, self.node_index)
+    }
+}
+
+impl<
+        'a,
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > VacantEntry<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts the key with `value` at the head of its bucket, returning a reference to the
+    /// value. Returns `None` if the table is full.
+    pub fn insert(self, value: V) -> Option<&'a mut V> {
+        self.insert_with(|| value)
+    }
+
+    fn insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        let node_index = self.table._push_front(self.bucket_index, self.key, f)?;
+        Some(&mut self.table.get_node_mut(node_index).value)
+    }
+}
+
 pub struct HashTableIterator<
     'a,
     K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
     V: Default + Copy + Clone + Pod + Zeroable,
     const NUM_BUCKETS: usize,
     const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static = DefaultBuildHasher,
 > {
-    ht: &'a HashTable<K, V, NUM_BUCKETS, MAX_SIZE>,
+    ht: &'a HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>,
     bucket: usize,
     node: u32,
+    rev_bucket: usize,
+    rev_node: u32,
+    // Both ends stop once every entry has been yielded from either one
+    remaining: usize,
 }
 
 impl<
@@ -401,12 +916,13 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > Iterator for HashTableIterator<'a, K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > Iterator for HashTableIterator<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.bucket < NUM_BUCKETS {
+        if self.remaining > 0 && self.bucket < NUM_BUCKETS {
             while self.node == SENTINEL {
                 self.bucket += 1;
                 if self.bucket == NUM_BUCKETS {
@@ -417,6 +933,7 @@ impl<
             }
             let node = self.ht.get_node(self.node);
             self.node = self.ht.get_next(self.node);
+            self.remaining -= 1;
             Some((&node.key, &node.value))
         } else {
             None
@@ -430,10 +947,22 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > DoubleEndedIterator for HashTableIterator<'a, K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > DoubleEndedIterator for HashTableIterator<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn next_back(&mut self) -> Option<Self::Item> {
-        None
+        if self.remaining == 0 {
+            return None;
+        }
+        // Walk the buckets backwards, and each chain from its tail
+        while self.rev_node == SENTINEL {
+            self.rev_bucket -= 1;
+            self.rev_node = self.ht.bucket_tail(self.rev_bucket);
+        }
+        let node = self.ht.get_node(self.rev_node);
+        self.rev_node = self.ht.get_prev(self.rev_node);
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
     }
 }
 
@@ -443,10 +972,15 @@ pub struct HashTableIteratorMut<
     V: Default + Copy + Clone + Pod + Zeroable,
     const NUM_BUCKETS: usize,
     const MAX_SIZE: usize,
+    S: BuildHasher + Default + 'static = DefaultBuildHasher,
 > {
-    ht: &'a mut HashTable<K, V, NUM_BUCKETS, MAX_SIZE>,
+    ht: &'a mut HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>,
     bucket: usize,
     node: u32,
+    rev_bucket: usize,
+    rev_node: u32,
+    // Both ends stop once every entry has been yielded from either one
+    remaining: usize,
 }
 
 impl<
@@ -455,12 +989,13 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > Iterator for HashTableIteratorMut<'a, K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > Iterator for HashTableIteratorMut<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     type Item = (&'a K, &'a mut V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.bucket < NUM_BUCKETS {
+        if self.remaining > 0 && self.bucket < NUM_BUCKETS {
             while self.node == SENTINEL {
                 self.bucket += 1;
                 if self.bucket == NUM_BUCKETS {
@@ -471,6 +1006,7 @@ impl<
             }
             let ptr = self.node;
             self.node = self.ht.get_next(self.node);
+            self.remaining -= 1;
             // TODO: How does one remove this unsafe?
             unsafe {
                 let node =
@@ -489,10 +1025,27 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > DoubleEndedIterator for HashTableIteratorMut<'a, K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > DoubleEndedIterator for HashTableIteratorMut<'a, K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn next_back(&mut self) -> Option<Self::Item> {
-        None
+        if self.remaining == 0 {
+            return None;
+        }
+        // Walk the buckets backwards, and each chain from its tail
+        while self.rev_node == SENTINEL {
+            self.rev_bucket -= 1;
+            self.rev_node = self.ht.bucket_tail(self.rev_bucket);
+        }
+        let ptr = self.rev_node;
+        self.rev_node = self.ht.get_prev(self.rev_node);
+        self.remaining -= 1;
+        // TODO: How does one remove this unsafe?
+        unsafe {
+            let node =
+                (*self.ht.allocator.nodes.as_mut_ptr().add((ptr - 1) as usize)).get_value_mut();
+            Some((&node.key, &mut node.value))
+        }
     }
 }
 
@@ -501,7 +1054,8 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > Index<&K> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > Index<&K> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     type Output = V;
 
@@ -515,9 +1069,548 @@ impl<
         V: Default + Copy + Clone + Pod + Zeroable,
         const NUM_BUCKETS: usize,
         const MAX_SIZE: usize,
-    > IndexMut<&K> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE>
+        S: BuildHasher + Default + 'static,
+    > IndexMut<&K> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
 {
     fn index_mut(&mut self, index: &K) -> &mut Self::Output {
         self.get_mut(index).unwrap()
     }
 }
+
+#[test]
+fn test_should_grow_and_rehash() {
+    type Small = HashTable<u64, u64, 8, 32>;
+    type Large = HashTable<u64, u64, 16, 64>;
+    let mut small_buf = vec![0u8; std::mem::size_of::<Small>()];
+    let small = Small::new_from_slice(small_buf.as_mut_slice());
+    let mut large_buf = vec![0u8; std::mem::size_of::<Large>()];
+    let large = Large::new_from_slice(large_buf.as_mut_slice());
+
+    for k in 0..24 {
+        assert!(!small.should_grow(3.0));
+        small.insert(k, k * 2).unwrap();
+    }
+    assert_eq!(small.load_factor(), 3.0);
+    assert!(!small.should_grow(3.0));
+    small.insert(24, 48).unwrap();
+    assert!(small.should_grow(3.0));
+
+    small.rehash_into(large).unwrap();
+    assert_eq!(large.len(), small.len());
+    for (k, v) in small.iter() {
+        assert_eq!(large.get(k), Some(v));
+    }
+    assert!(!large.should_grow(3.0));
+
+    // The target must have room for every entry
+    let mut tiny_buf = vec![0u8; std::mem::size_of::<HashTable<u64, u64, 2, 8>>()];
+    let tiny = HashTable::<u64, u64, 2, 8>::new_from_slice(tiny_buf.as_mut_slice());
+    assert!(small.rehash_into(tiny).is_none());
+    assert!(tiny.is_empty());
+}
+
+#[test]
+fn test_get_or_insert_full_table_is_unchanged() {
+    type Table = HashTable<u64, u64, 4, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    for k in 0..16 {
+        assert_eq!(table.get_or_insert(k, k + 1), Some(&mut (k + 1)));
+    }
+    // Existing keys are returned without being overwritten
+    *table.get_or_insert(3, 100).unwrap() += 10;
+    assert_eq!(table.get(&3), Some(&14));
+
+    let snapshot = bytemuck::bytes_of(&*table).to_vec();
+    assert!(table.get_or_insert(16, 17).is_none());
+    assert!(table.insert(16, 17).is_none());
+    assert_eq!(bytemuck::bytes_of(&*table), snapshot.as_slice());
+}
+
+#[test]
+fn test_overwrite_promotes_to_bucket_head() {
+    // All keys are picked from the same bucket so that the order of its chain is observable
+    type Table = HashTable<u64, u64, 2, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    fn chain(table: &Table, bucket: usize) -> Vec<u64> {
+        let mut keys = vec![];
+        let mut node = table.buckets[bucket];
+        let mut prev = SENTINEL;
+        while node != SENTINEL {
+            assert_eq!(table.get_prev(node), prev);
+            keys.push(table.get_node(node).key);
+            prev = node;
+            node = table.get_next(node);
+        }
+        keys
+    }
+    let (mut keys, mut bucket) = (vec![], None);
+    for k in 0..64 {
+        let b = table.bucket_index(&k);
+        if *bucket.get_or_insert(b) == b && keys.len() < 4 {
+            keys.push(k);
+            table.insert(k, k);
+        }
+    }
+    let bucket = bucket.unwrap();
+    // New keys are inserted at the head
+    assert_eq!(
+        chain(table, bucket),
+        vec![keys[3], keys[2], keys[1], keys[0]]
+    );
+
+    // Overwriting an existing key promotes it, just like get_mut
+    table.insert(keys[1], 100);
+    assert_eq!(
+        chain(table, bucket),
+        vec![keys[1], keys[3], keys[2], keys[0]]
+    );
+    table.get_mut(&keys[0]);
+    assert_eq!(
+        chain(table, bucket),
+        vec![keys[0], keys[1], keys[3], keys[2]]
+    );
+    table.get_or_insert(keys[2], 0);
+    assert_eq!(
+        chain(table, bucket),
+        vec![keys[2], keys[0], keys[1], keys[3]]
+    );
+
+    // Shared lookups do not reorder the bucket
+    table.get(&keys[3]);
+    assert_eq!(
+        chain(table, bucket),
+        vec![keys[2], keys[0], keys[1], keys[3]]
+    );
+    assert_eq!(table.get(&keys[1]), Some(&100));
+}
+
+#[test]
+fn test_iter_rev() {
+    use rand::Rng;
+    type Table = HashTable<u64, u64, 16, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    assert!(table.iter().next_back().is_none());
+    let mut rng = rand::thread_rng();
+    for _ in 0..200 {
+        let k = rng.gen_range(0, 1000);
+        table.insert(k, k * 2);
+    }
+    for _ in 0..50 {
+        table.remove(&rng.gen_range(0, 1000));
+    }
+    let forward = table.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+    assert_eq!(forward.len(), table.len());
+    let mut backward = table
+        .iter()
+        .rev()
+        .map(|(k, v)| (*k, *v))
+        .collect::<Vec<_>>();
+    backward.reverse();
+    assert_eq!(backward, forward);
+
+    // The iterator type names default to the default hasher like the table does
+    let typed: HashTableIterator<'_, u64, u64, 16, 256> = (&*table).into_iter();
+    assert_eq!(typed.count(), table.len());
+
+    // Alternating ends yields every entry exactly once
+    let mut iter = table.iter();
+    let (mut front, mut back) = (vec![], vec![]);
+    loop {
+        if rng.gen::<bool>() {
+            match iter.next() {
+                Some((k, v)) => front.push((*k, *v)),
+                None => break,
+            }
+        } else {
+            match iter.next_back() {
+                Some((k, v)) => back.push((*k, *v)),
+                None => break,
+            }
+        }
+    }
+    assert!(iter.next().is_none() && iter.next_back().is_none());
+    drop(iter);
+    back.reverse();
+    front.extend(back);
+    assert_eq!(front, forward);
+
+    for (_, v) in table.iter_mut().rev() {
+        *v += 1;
+    }
+    assert!(table.iter().all(|(k, v)| *v == k * 2 + 1));
+}
+
+/// Hashes a u64 to itself, so keys are placed in bucket `key % NUM_BUCKETS`
+#[cfg(test)]
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+#[cfg(test)]
+impl std::hash::Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8) | *byte as u64;
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
+
+#[test]
+fn test_custom_hasher() {
+    type Table = HashTable<u64, u64, 8, 64, BuildHasherDefault<IdentityHasher>>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    // The hasher does not change the layout of the table
+    assert_eq!(
+        std::mem::size_of::<Table>(),
+        std::mem::size_of::<HashTable<u64, u64, 8, 64>>()
+    );
+    for k in 0..64 {
+        table.insert(k, k * 2).unwrap();
+    }
+    for bucket in 0..8 {
+        let mut node = table.buckets[bucket];
+        let mut count = 0;
+        while node != SENTINEL {
+            assert_eq!(table.get_node(node).key % 8, bucket as u64);
+            node = table.get_next(node);
+            count += 1;
+        }
+        assert_eq!(count, 8);
+    }
+    for k in 0..64 {
+        assert_eq!(table.get(&k), Some(&(k * 2)));
+    }
+    assert_eq!(table.remove(&9), Some(18));
+    assert!(!table.contains(&9));
+
+    // Entries can be moved between tables with different hashers
+    let mut other_buf = vec![0u8; std::mem::size_of::<HashTable<u64, u64, 16, 64>>()];
+    let other = HashTable::<u64, u64, 16, 64>::new_from_slice(other_buf.as_mut_slice());
+    table.rehash_into(other).unwrap();
+    assert!(table.iter().all(|(k, v)| other.get(k) == Some(v)));
+}
+
+#[test]
+#[should_panic(expected = "S must be a zero-sized, deterministic hasher builder")]
+fn test_random_state_is_rejected() {
+    type Table = HashTable<u64, u64, 8, 64, std::collections::hash_map::RandomState>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    Table::new_from_slice(buf.as_mut_slice());
+}
+
+#[test]
+fn test_retain() {
+    // Two buckets guarantee long chains of colliding keys
+    type Table = HashTable<u64, u64, 2, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    fn chain(table: &Table, bucket: usize) -> Vec<u64> {
+        let mut keys = vec![];
+        let (mut node, mut prev) = (table.buckets[bucket], SENTINEL);
+        while node != SENTINEL {
+            assert_eq!(table.get_prev(node), prev);
+            keys.push(table.get_node(node).key);
+            prev = node;
+            node = table.get_next(node);
+        }
+        keys
+    }
+    for k in 0..64 {
+        table.insert(k, k).unwrap();
+    }
+
+    // Drop the head and the tail of each chain, along with every third key
+    let chains = [chain(table, 0), chain(table, 1)];
+    let ends = chains
+        .iter()
+        .flat_map(|c| [c[0], c[c.len() - 1]])
+        .collect::<Vec<_>>();
+    let keep = |k: &u64| !k.is_multiple_of(3) && !ends.contains(k);
+    table.retain(|k, v| {
+        *v += 1;
+        keep(k)
+    });
+    for (bucket, before) in chains.iter().enumerate() {
+        let expected = before.iter().copied().filter(keep).collect::<Vec<_>>();
+        assert_eq!(chain(table, bucket), expected);
+    }
+    let survivors = (0..64).filter(keep).collect::<Vec<_>>();
+    assert_eq!(table.len(), survivors.len());
+    for k in 0..64 {
+        if keep(&k) {
+            assert_eq!(table.get(&k), Some(&(k + 1)));
+        } else {
+            assert!(!table.contains(&k));
+        }
+    }
+
+    // Empty one bucket entirely, leaving a single node in the other
+    let last = *chain(table, 1).last().unwrap();
+    table.retain(|k, _| *k == last);
+    assert_eq!(table.buckets[0], SENTINEL);
+    assert_eq!(chain(table, 1), vec![last]);
+    table.retain(|_, _| false);
+    assert_eq!(table.buckets, [SENTINEL; 2]);
+    assert!(table.is_empty());
+
+    // Freed nodes are reused
+    for k in 0..64 {
+        table.insert(k, k).unwrap();
+    }
+    assert_eq!(table.len(), 64);
+}
+
+#[test]
+fn test_entry() {
+    type Table = HashTable<u64, u64, 2, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    let head_key =
+        |table: &Table, key: u64| table.get_node(table.buckets[table.bucket_index(&key)]).key;
+
+    // Inserted keys end up at the head of their bucket
+    for k in 0..6 {
+        assert_eq!(table.entry(k).or_insert(k * 10), Some(&mut (k * 10)));
+        assert_eq!(head_key(table, k), k);
+    }
+    // Accessing an existing key moves it to the head and keeps its value
+    for k in 0..6 {
+        *table.entry(k).or_insert(0).unwrap() += 1;
+        assert_eq!(head_key(table, k), k);
+        assert_eq!(table.get(&k), Some(&(k * 10 + 1)));
+    }
+    table.entry(2).and_modify(|v| *v = 7).or_insert(100);
+    assert_eq!(table.get(&2), Some(&7));
+    assert_eq!(head_key(table, 2), 2);
+    table.entry(6).and_modify(|v| *v = 7).or_default();
+    assert_eq!(table.get(&6), Some(&0));
+
+    match table.entry(3) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), &3);
+            assert_eq!(entry.insert(33), 31);
+            assert_eq!(entry.remove(), 33);
+        }
+        Entry::Vacant(_) => panic!("key 3 should be present"),
+    }
+    assert!(!table.contains(&3));
+    assert_eq!(table.len(), 6);
+
+    // A vacant entry cannot be filled once the table is full
+    table.entry(7).or_insert(70).unwrap();
+    table.entry(8).or_insert(80).unwrap();
+    let snapshot = bytemuck::bytes_of(&*table).to_vec();
+    assert!(table
+        .entry(9)
+        .or_insert_with(|| panic!("not called"))
+        .is_none());
+    assert_eq!(bytemuck::bytes_of(&*table), snapshot.as_slice());
+    assert!(table.entry(8).or_insert(0).is_some());
+}
+
+#[test]
+fn test_clear() {
+    type Table = HashTable<u64, u64, 4, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    for round in 0..3 {
+        for k in 0..16 {
+            assert!(table.insert(k + round, k).is_some());
+        }
+        assert!(table.insert(100, 100).is_none());
+        table.remove(&(3 + round));
+        table.clear();
+        assert!(table.is_empty());
+        assert!(table.buckets.iter().all(|b| *b == SENTINEL));
+        assert_eq!(table.iter().count(), 0);
+        for k in 0..16 {
+            assert!(!table.contains(&(k + round)));
+        }
+    }
+    for k in 0..16 {
+        assert!(table.insert(k, k * 2).is_some());
+    }
+    assert_eq!(table.len(), table.capacity());
+    for k in 0..16 {
+        assert_eq!(table.get(&k), Some(&(k * 2)));
+    }
+}
+
+#[test]
+fn test_chain_len_stats() {
+    type Table = HashTable<u64, u64, 8, 64, BuildHasherDefault<IdentityHasher>>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    assert_eq!(table.max_chain_len(), 0);
+    assert_eq!(table.chain_len_histogram(), vec![8]);
+    // One key in each bucket, then force 10 collisions into bucket 3
+    for k in 0..8 {
+        table.insert(k, k).unwrap();
+    }
+    assert_eq!(table.max_chain_len(), 1);
+    assert_eq!(table.chain_len_histogram(), vec![0, 8]);
+    for i in 1..=10 {
+        table.insert(3 + 8 * i, i).unwrap();
+    }
+    assert_eq!(table.load_factor(), 18.0 / 8.0);
+    assert_eq!(table.max_chain_len(), 11);
+    let histogram = table.chain_len_histogram();
+    assert_eq!(histogram.len(), 12);
+    assert_eq!(histogram[1], 7);
+    assert_eq!(histogram[11], 1);
+    assert_eq!(histogram.iter().sum::<usize>(), 8);
+    table.remove(&11);
+    assert_eq!(table.max_chain_len(), 10);
+}
+
+#[test]
+fn test_sip_hasher_test_vectors() {
+    use rand::Rng;
+    // Reference SipHash-1-3 outputs for the key 00 01 .. 0f and the message 00 01 .. (len - 1)
+    let vectors = [
+        (0, 0xabac0158050fc4dc),
+        (1, 0xc9f49bf37d57ca93),
+        (7, 0xd3927d989bb11140),
+        (8, 0x369095118d299a8e),
+        (9, 0x25a48eb36c063de4),
+        (15, 0xd320d86d2a519956),
+        (16, 0xcc4fdd1a7d908b66),
+        (31, 0x2370dd1f8c21d1bc),
+        (63, 0x9d199062b7bbb3a8),
+    ];
+    let k0 = u64::from_le_bytes([0, 1, 2, 3, 4, 5, 6, 7]);
+    let k1 = u64::from_le_bytes([8, 9, 10, 11, 12, 13, 14, 15]);
+    let mut rng = rand::thread_rng();
+    for (len, expected) in vectors {
+        let message = (0..len).collect::<Vec<u8>>();
+        let mut hasher = SipHasher13::new_with_keys(k0, k1);
+        hasher.write(&message);
+        assert_eq!(hasher.finish(), expected, "message of length {}", len);
+        // Splitting the input exercises the buffering of partial words
+        let split = rng.gen_range(0, len as usize + 1);
+        let mut hasher = SipHasher13::new_with_keys(k0, k1);
+        hasher.write(&message[..split]);
+        hasher.write(&message[split..]);
+        assert_eq!(hasher.finish(), expected, "message split at {}", split);
+    }
+}
+
+#[test]
+fn test_default_hasher_bucket_indices() {
+    // Bucket placement is part of the on-chain layout of a table, so these must never change
+    type Table = HashTable<u64, u64, 16, 64>;
+    let table = Table::new();
+    let buckets = [0u64, 1, 42, u64::MAX].map(|k| table.bucket_index(&k));
+    assert_eq!(buckets, [5, 9, 1, 13]);
+}
+
+#[test]
+fn test_get_or_insert_with_and_insert_if_absent() {
+    type Table = HashTable<u64, u64, 4, 8>;
+    let mut table = Table::new();
+    let mut calls = 0;
+    let mut f = |v| {
+        calls += 1;
+        v
+    };
+    *table.get_or_insert_with(1, || f(10)).unwrap() += 1;
+    assert_eq!(table.get_or_insert_with(1, || f(20)), Some(&mut 11));
+    assert_eq!(calls, 1);
+
+    assert!(table.insert_if_absent(2, 20));
+    assert!(!table.insert_if_absent(2, 30));
+    assert_eq!(table.get(&2), Some(&20));
+    for k in 3..9 {
+        assert!(table.insert_if_absent(k, k));
+    }
+    // The table is full, so only present keys succeed and `f` is not called for missing ones
+    assert!(!table.insert_if_absent(9, 9));
+    assert!(table
+        .get_or_insert_with(9, || panic!("table is full"))
+        .is_none());
+    assert_eq!(
+        table.get_or_insert_with(8, || panic!("key is present")),
+        Some(&mut 8)
+    );
+    assert_eq!(table.len(), 8);
+}
+
+#[test]
+fn test_iter_indexed_and_remove_at() {
+    type Table = HashTable<u64, u64, 8, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    for k in 0..64 {
+        table.insert(k, k * 2);
+    }
+    let indexed = table
+        .iter_indexed()
+        .map(|(i, k, v)| (i, *k, *v))
+        .collect::<Vec<_>>();
+    assert_eq!(indexed.len(), 64);
+    for (i, k, v) in indexed.iter() {
+        let node = table.get_node(*i);
+        assert_eq!((node.key, node.value), (*k, *v));
+    }
+    assert!(indexed.iter().map(|(_, k, v)| (k, v)).eq(table.iter()));
+
+    // Conditional deletes by address
+    for (i, k, v) in indexed.iter() {
+        if k % 3 == 0 {
+            assert_eq!(table.remove_at(*i), Some(*v));
+        }
+    }
+    assert_eq!(table.len(), 64 - 22);
+    assert!((0..64).all(|k| table.contains(&k) == (k % 3 != 0)));
+    assert_eq!(table.remove_at(SENTINEL), None);
+    assert_eq!(table.remove_at(65), None);
+    for k in 0..64 {
+        table.insert(k, k);
+    }
+    assert_eq!(table.len(), 64);
+}
+
+#[test]
+fn test_remove_at_stale_index() {
+    type Table = HashTable<u64, u64, 8, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Table>()];
+    let table = Table::new_from_slice(buf.as_mut_slice());
+    for k in 0..4 {
+        table.insert(k, k + 1);
+    }
+    let (index, key, value) = table
+        .iter_indexed()
+        .map(|(i, k, v)| (i, *k, *v))
+        .next()
+        .unwrap();
+    assert_eq!(table.remove_at(index), Some(value));
+    assert_eq!(table.len(), 3);
+    // A handle to an entry that was already removed
+    assert_eq!(table.remove_at(index), None);
+    assert_eq!(table.len(), 3);
+    // An in-range slot that was never allocated
+    assert_eq!(table.remove_at(10), None);
+    assert_eq!(table.len(), 3);
+    assert!(!table.contains(&key));
+
+    // The free list is intact, so every slot is handed out exactly once
+    for k in 100..113 {
+        assert!(table.insert(k, k).is_some());
+    }
+    assert_eq!(table.len(), 16);
+    assert!(table.insert(200, 200).is_none());
+    let mut indices = table.iter_indexed().map(|(i, _, _)| i).collect::<Vec<_>>();
+    indices.sort_unstable();
+    assert_eq!(indices, (1..=16).collect::<Vec<_>>());
+}
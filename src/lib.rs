@@ -1,9 +1,38 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+/// `println!` with the `std` feature. Without `std` there is no stdout, so the message is only
+/// type checked and then dropped.
+macro_rules! std_println {
+    ($($arg:tt)*) => {{
+        #[cfg(test)]
+        crate::PRINTED_LINES.with(|lines| lines.set(lines.get() + 1));
+        #[cfg(feature = "std")]
+        std::println!($($arg)*);
+        #[cfg(not(feature = "std"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(test)]
+std::thread_local! {
+    /// Number of lines printed by `std_println!` on the current thread, so tests can check that an
+    /// operation prints nothing
+    static PRINTED_LINES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
 pub mod avl_tree;
+pub mod binary_heap;
 pub mod critbit;
 pub mod deque;
+pub mod error;
+pub mod hash_set;
 pub mod hash_table;
 pub mod node_allocator;
 pub mod red_black_tree;
+#[cfg(feature = "serde")]
+mod serde_impls;
 
 pub use node_allocator::FromSlice;
 pub use node_allocator::NodeAllocatorMap;
@@ -12,8 +41,14 @@ pub use node_allocator::ZeroCopy;
 pub use node_allocator::SENTINEL;
 
 pub use avl_tree::AVLTree;
+pub use binary_heap::Heap;
+pub use binary_heap::MinHeap;
 pub use critbit::Critbit;
+pub use critbit::CritbitKey;
 pub use deque::Deque;
+pub use error::SokobanError;
+pub use error::ZeroCopyError;
+pub use hash_set::HashSet;
 pub use hash_table::HashTable;
 pub use node_allocator::NodeAllocator;
 pub use red_black_tree::RedBlackTree;
@@ -1,6 +1,8 @@
+use crate::error::ZeroCopyError;
+use alloc::boxed::Box;
 use bytemuck::{Pod, Zeroable};
+use core::mem::{align_of, size_of};
 use num_derive::FromPrimitive;
-use std::mem::{align_of, size_of};
 
 /// Enum representing the fields of a tree node:
 /// 0 - left pointer
@@ -24,6 +26,32 @@ pub enum NodeField {
     Right = 1,
 }
 
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for super::TreeField {}
+    impl Sealed for super::NodeField {}
+}
+
+/// A named node register, accepted by the typed accessors of `NodeAllocator` such as `get_field`.
+/// This trait is sealed and only implemented for `TreeField` and `NodeField`.
+pub trait NodeRegister: sealed::Sealed + Copy {
+    fn register(self) -> u32;
+}
+
+impl NodeRegister for TreeField {
+    #[inline(always)]
+    fn register(self) -> u32 {
+        self as u32
+    }
+}
+
+impl NodeRegister for NodeField {
+    #[inline(always)]
+    fn register(self) -> u32 {
+        self as u32
+    }
+}
+
 /// This is a convenience trait that exposes an interface to read a struct from an arbitrary byte array
 pub trait FromSlice {
     fn new_from_slice(data: &mut [u8]) -> &mut Self;
@@ -44,6 +72,9 @@ pub trait NodeAllocatorMap<K, V> {
         self.len() == 0
     }
     fn capacity(&self) -> usize;
+    /// Removes every entry in place. The container keeps its buffer and can be refilled up to
+    /// its capacity.
+    fn clear(&mut self);
     fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&K, &V)> + '_>;
     fn iter_mut(&mut self) -> Box<dyn DoubleEndedIterator<Item = (&K, &mut V)> + '_>;
 }
@@ -58,20 +89,43 @@ pub trait OrderedNodeAllocatorMap<K, V>: NodeAllocatorMap<K, V> {
 
 pub trait ZeroCopy: Pod {
     fn load_mut_bytes(data: &'_ mut [u8]) -> Option<&'_ mut Self> {
-        let size = std::mem::size_of::<Self>();
-        bytemuck::try_from_bytes_mut(&mut data[..size]).ok()
+        Self::try_load_mut_bytes(data).ok()
     }
 
     fn load_bytes(data: &'_ [u8]) -> Option<&'_ Self> {
-        let size = std::mem::size_of::<Self>();
-        bytemuck::try_from_bytes(&data[..size]).ok()
+        Self::try_load_bytes(data).ok()
+    }
+
+    /// Like `load_mut_bytes`, but reports why the slice could not be loaded
+    fn try_load_mut_bytes(data: &'_ mut [u8]) -> Result<&'_ mut Self, ZeroCopyError> {
+        check_slice::<Self>(data)?;
+        Ok(bytemuck::from_bytes_mut(&mut data[..size_of::<Self>()]))
+    }
+
+    /// Like `load_bytes`, but reports why the slice could not be loaded
+    fn try_load_bytes(data: &'_ [u8]) -> Result<&'_ Self, ZeroCopyError> {
+        check_slice::<Self>(data)?;
+        Ok(bytemuck::from_bytes(&data[..size_of::<Self>()]))
+    }
+}
+
+fn check_slice<T>(data: &[u8]) -> Result<(), ZeroCopyError> {
+    if data.len() < size_of::<T>() {
+        return Err(ZeroCopyError::SliceTooShort {
+            needed: size_of::<T>(),
+            got: data.len(),
+        });
     }
+    if !(data.as_ptr() as usize).is_multiple_of(align_of::<T>()) {
+        return Err(ZeroCopyError::Misaligned);
+    }
+    Ok(())
 }
 
 pub const SENTINEL: u32 = 0;
 
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct Node<T: Copy + Clone + Pod + Zeroable + Default, const NUM_REGISTERS: usize> {
     /// Arbitrary registers (generally used for pointers)
     /// Note: Register 0 is ALWAYS used for the free list
@@ -130,6 +184,14 @@ impl<T: Copy + Clone + Pod + Zeroable + Default, const NUM_REGISTERS: usize>
     }
 }
 
+/// Fixed-capacity slab of `MAX_SIZE` nodes, addressed by the indices `1..=MAX_SIZE` (`SENTINEL`
+/// is 0). `MAX_SIZE` must be at least 1, which is checked when the allocator is constructed or
+/// initialized:
+///
+/// ```compile_fail
+/// use sokoban::NodeAllocator;
+/// let allocator = NodeAllocator::<u64, 0, 2>::new();
+/// ```
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct NodeAllocator<
@@ -183,6 +245,7 @@ impl<
 {
     fn default() -> Self {
         assert!(NUM_REGISTERS >= 1);
+        Self::assert_valid_size();
         let na = NodeAllocator {
             size: 0,
             bump_index: 1,
@@ -200,6 +263,20 @@ impl<
         const NUM_REGISTERS: usize,
     > NodeAllocator<T, MAX_SIZE, NUM_REGISTERS>
 {
+    const VALID_SIZE: () = {
+        assert!(MAX_SIZE >= 1, "NodeAllocator: MAX_SIZE must be at least 1");
+        assert!(
+            MAX_SIZE < u32::MAX as usize,
+            "NodeAllocator: MAX_SIZE must be less than u32::MAX"
+        );
+    };
+
+    fn assert_valid_size() {
+        // Referencing the constant forces it to be evaluated for this instantiation
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_SIZE;
+    }
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -207,20 +284,20 @@ impl<
     #[inline(always)]
     fn assert_proper_alignemnt(&self) {
         let reg_size = size_of::<u32>() * NUM_REGISTERS;
-        let self_ptr = std::slice::from_ref(self).as_ptr() as usize;
-        let node_ptr = std::slice::from_ref(&self.nodes).as_ptr() as usize;
+        let self_ptr = core::slice::from_ref(self).as_ptr() as usize;
+        let node_ptr = core::slice::from_ref(&self.nodes).as_ptr() as usize;
         let self_align = align_of::<Self>();
         let t_index = node_ptr + reg_size;
         let t_align = align_of::<T>();
         let t_size = size_of::<T>();
         assert!(
-            self_ptr % self_align as usize == 0,
+            self_ptr.is_multiple_of(self_align),
             "NodeAllocator alignment mismatch, address is {} which is not a multiple of the struct alignment ({})",
             self_ptr,
             self_align,
         );
         assert!(
-            t_size % t_align == 0,
+            t_size.is_multiple_of(t_align),
             "Size of T ({}) is not a multiple of the alignment of T ({})",
             t_size,
             t_align,
@@ -232,15 +309,19 @@ impl<
             self_align,
         );
         assert!(node_ptr == self_ptr + 16, "Nodes are misaligned");
-        assert!(t_index % t_align == 0, "First index of T is misaligned");
         assert!(
-            (t_index + t_size + reg_size) % t_align == 0,
+            t_index.is_multiple_of(t_align),
+            "First index of T is misaligned"
+        );
+        assert!(
+            (t_index + t_size + reg_size).is_multiple_of(t_align),
             "Subsequent indices of T are misaligned"
         );
     }
 
     pub fn initialize(&mut self) {
         assert!(NUM_REGISTERS >= 1);
+        Self::assert_valid_size();
         self.assert_proper_alignemnt();
         if self.size == 0 && self.bump_index == 0 && self.free_list_head == 0 {
             self.bump_index = 1;
@@ -250,6 +331,72 @@ impl<
         }
     }
 
+    /// Returns an initialized allocator to its empty state in place. Registers of every node
+    /// handed out since initialization are cleared so that reallocated nodes start unlinked, which
+    /// makes this O(number of nodes ever allocated) rather than O(`MAX_SIZE`). Values are left as
+    /// is and are overwritten by `add_node`.
+    pub fn reset(&mut self) {
+        for i in 1..self.bump_index {
+            self.get_mut(i).registers = [SENTINEL; NUM_REGISTERS];
+        }
+        self.size = 0;
+        self.bump_index = 1;
+        self.free_list_head = 1;
+    }
+
+    /// Makes `self` an exact copy of `other`. Only the slots that `other` has handed out (those
+    /// below its bump index) are copied, so copying a partially full allocator does not touch the
+    /// rest of the node array beyond clearing the registers of slots that `self` had used.
+    pub fn copy_from(&mut self, other: &Self) {
+        let used = other.bump_index.saturating_sub(1) as usize;
+        let previously_used = self.bump_index.saturating_sub(1) as usize;
+        self.nodes[..used].copy_from_slice(&other.nodes[..used]);
+        for node in self.nodes[used..previously_used.max(used)].iter_mut() {
+            node.registers = [SENTINEL; NUM_REGISTERS];
+        }
+        self.size = other.size;
+        self.bump_index = other.bump_index;
+        self.free_list_head = other.free_list_head;
+    }
+
+    /// Returns the number of allocated nodes
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the maximum number of nodes that can be allocated. Node indices run from 1 to
+    /// `MAX_SIZE` (index 0 is reserved for `SENTINEL` but does not occupy a slot in `nodes`),
+    /// so all `MAX_SIZE` nodes are usable.
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        MAX_SIZE
+    }
+
+    /// Returns true if no more nodes can be allocated
+    #[inline(always)]
+    pub fn is_full(&self) -> bool {
+        self.len() >= MAX_SIZE
+    }
+
+    /// Returns the number of nodes that can still be allocated
+    #[inline(always)]
+    pub fn remaining_capacity(&self) -> usize {
+        MAX_SIZE - self.len()
+    }
+
+    /// Returns the number of previously allocated nodes that are on the free list, waiting to be
+    /// reused before new slots are taken from the bump index
+    #[inline(always)]
+    pub fn free_count(&self) -> usize {
+        self.bump_index.saturating_sub(1) as usize - self.len()
+    }
+
     #[inline(always)]
     pub fn get(&self, i: u32) -> &Node<T, NUM_REGISTERS> {
         &self.nodes[(i - 1) as usize]
@@ -260,6 +407,24 @@ impl<
         &mut self.nodes[(i - 1) as usize]
     }
 
+    /// Returns the node at index `i`, or `None` if `i` is `SENTINEL` or past the last index
+    /// (`MAX_SIZE`, since indices are 1-based)
+    #[inline(always)]
+    pub fn try_get(&self, i: u32) -> Option<&Node<T, NUM_REGISTERS>> {
+        if i == SENTINEL {
+            return None;
+        }
+        self.nodes.get((i - 1) as usize)
+    }
+
+    #[inline(always)]
+    pub fn try_get_mut(&mut self, i: u32) -> Option<&mut Node<T, NUM_REGISTERS>> {
+        if i == SENTINEL {
+            return None;
+        }
+        self.nodes.get_mut((i - 1) as usize)
+    }
+
     /// Adds a new node to the allocator. The function returns the current pointer
     /// to the free list, where the new node is inserted
     pub fn add_node(&mut self, node: T) -> u32 {
@@ -336,4 +501,301 @@ impl<
             SENTINEL
         }
     }
+
+    // Typed register accessors. These only accept a `TreeField` or `NodeField` and check the
+    // register index in debug builds, so passing a field that does not exist for this allocator
+    // is caught early. Use the raw methods above for registers that are not named by a field.
+
+    #[inline(always)]
+    fn field_register(f: impl NodeRegister) -> u32 {
+        let r = f.register();
+        debug_assert!(
+            (r as usize) < NUM_REGISTERS,
+            "Register {} is out of bounds for a node with {} registers",
+            r,
+            NUM_REGISTERS
+        );
+        r
+    }
+
+    #[inline(always)]
+    pub fn get_field(&self, i: u32, f: impl NodeRegister) -> u32 {
+        self.get_register(i, Self::field_register(f))
+    }
+
+    #[inline(always)]
+    pub fn set_field(&mut self, i: u32, value: u32, f: impl NodeRegister) {
+        self.set_register(i, value, Self::field_register(f))
+    }
+
+    #[inline(always)]
+    pub fn clear_field(&mut self, i: u32, f: impl NodeRegister) {
+        self.clear_register(i, Self::field_register(f))
+    }
+
+    #[inline(always)]
+    pub fn connect_fields(
+        &mut self,
+        i: u32,
+        j: u32,
+        f_i: impl NodeRegister,
+        f_j: impl NodeRegister,
+    ) {
+        self.connect(i, j, Self::field_register(f_i), Self::field_register(f_j))
+    }
+
+    #[inline(always)]
+    pub fn disconnect_fields(
+        &mut self,
+        i: u32,
+        j: u32,
+        f_i: impl NodeRegister,
+        f_j: impl NodeRegister,
+    ) {
+        self.disconnect(i, j, Self::field_register(f_i), Self::field_register(f_j))
+    }
+}
+
+#[test]
+fn test_len_and_capacity() {
+    type Allocator = NodeAllocator<u64, 16, 2>;
+    let mut buf = vec![0u8; std::mem::size_of::<Allocator>()];
+    let allocator = Allocator::load_mut_bytes(buf.as_mut_slice()).unwrap();
+    allocator.initialize();
+    assert!(allocator.is_empty());
+    assert_eq!(allocator.capacity(), 16);
+    let indices = (0..16).map(|i| allocator.add_node(i)).collect::<Vec<_>>();
+    assert_eq!(allocator.len(), allocator.capacity());
+    assert!(!allocator.is_empty());
+    assert!(indices.iter().all(|i| (1..=16).contains(i)));
+    allocator.remove_node(indices[3]);
+    assert_eq!(allocator.len(), 15);
+    assert_eq!(allocator.add_node(3), indices[3]);
+    assert_eq!(allocator.len(), 16);
+}
+
+#[test]
+fn test_remaining_capacity_and_free_count() {
+    type Allocator = NodeAllocator<u64, 16, 2>;
+    let mut buf = vec![0u8; std::mem::size_of::<Allocator>()];
+    let allocator = Allocator::load_mut_bytes(buf.as_mut_slice()).unwrap();
+    allocator.initialize();
+    assert_eq!(allocator.remaining_capacity(), 16);
+    assert_eq!(allocator.free_count(), 0);
+    assert!(!allocator.is_full());
+    let mut indices = vec![];
+    for i in 0..16 {
+        indices.push(allocator.add_node(i));
+        assert_eq!(allocator.remaining_capacity(), 15 - i as usize);
+        assert_eq!(allocator.free_count(), 0);
+    }
+    assert!(allocator.is_full());
+    for (i, index) in indices.iter().enumerate() {
+        allocator.remove_node(*index);
+        assert!(!allocator.is_full());
+        assert_eq!(allocator.remaining_capacity(), i + 1);
+        assert_eq!(allocator.free_count(), i + 1);
+    }
+    assert!(allocator.is_empty());
+    for i in 0..4 {
+        allocator.add_node(i);
+    }
+    assert_eq!(allocator.remaining_capacity(), 12);
+    assert_eq!(allocator.free_count(), 12);
+    allocator.reset();
+    assert_eq!(allocator.remaining_capacity(), 16);
+    assert_eq!(allocator.free_count(), 0);
+}
+
+#[test]
+fn test_try_get() {
+    type Allocator = NodeAllocator<u64, 16, 2>;
+    let mut buf = vec![0u8; std::mem::size_of::<Allocator>()];
+    let allocator = Allocator::load_mut_bytes(buf.as_mut_slice()).unwrap();
+    allocator.initialize();
+    let indices = (0..16)
+        .map(|i| allocator.add_node(i * 10))
+        .collect::<Vec<_>>();
+    assert!(allocator.try_get(SENTINEL).is_none());
+    assert!(allocator.try_get_mut(SENTINEL).is_none());
+    assert_eq!(
+        allocator.try_get(indices[4]).map(|n| *n.get_value()),
+        Some(40)
+    );
+    assert_eq!(allocator.try_get(16).map(|n| *n.get_value()), Some(150));
+    assert!(allocator.try_get(17).is_none());
+    assert!(allocator.try_get(u32::MAX).is_none());
+    allocator.try_get_mut(16).unwrap().set_value(7);
+    assert_eq!(*allocator.get(16).get_value(), 7);
+    assert!(allocator.try_get_mut(17).is_none());
+}
+
+#[test]
+fn test_try_load_bytes() {
+    type Allocator = NodeAllocator<u64, 16, 2>;
+    let size = std::mem::size_of::<Allocator>();
+    // u64 backing storage guarantees 8-byte alignment for the buffer
+    let mut words = vec![0u64; size / 8 + 1];
+    let buf = bytemuck::cast_slice_mut::<u64, u8>(words.as_mut_slice());
+    assert!(Allocator::try_load_mut_bytes(buf).is_ok());
+    assert!(Allocator::try_load_bytes(&buf[..size]).is_ok());
+    assert_eq!(
+        Allocator::try_load_mut_bytes(&mut buf[..size - 1]).err(),
+        Some(ZeroCopyError::SliceTooShort {
+            needed: size,
+            got: size - 1
+        })
+    );
+    assert_eq!(
+        Allocator::try_load_bytes(&[]).err(),
+        Some(ZeroCopyError::SliceTooShort {
+            needed: size,
+            got: 0
+        })
+    );
+    assert!(Allocator::load_bytes(&buf[..size - 1]).is_none());
+    assert_eq!(
+        Allocator::try_load_mut_bytes(&mut buf[1..]).err(),
+        Some(ZeroCopyError::Misaligned)
+    );
+    assert_eq!(
+        Allocator::try_load_bytes(&buf[4..]).err(),
+        Some(ZeroCopyError::Misaligned)
+    );
+    assert!(Allocator::load_mut_bytes(&mut buf[1..]).is_none());
+}
+
+#[test]
+#[should_panic(expected = "SliceTooShort")]
+fn test_new_from_slice_too_short() {
+    use crate::Deque;
+    type D = Deque<u64, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<D>() - 1];
+    D::new_from_slice(buf.as_mut_slice());
+}
+
+#[test]
+fn test_copy_from() {
+    type Allocator = NodeAllocator<u64, 16, 2>;
+    let mut src_buf = vec![0u8; std::mem::size_of::<Allocator>()];
+    let mut dst_buf = vec![0u8; std::mem::size_of::<Allocator>()];
+    let src = Allocator::load_mut_bytes(src_buf.as_mut_slice()).unwrap();
+    let dst = Allocator::load_mut_bytes(dst_buf.as_mut_slice()).unwrap();
+    src.initialize();
+    dst.initialize();
+    // dst has used more slots than src, and those must come back unlinked
+    for i in 0..12 {
+        let index = dst.add_node(i);
+        dst.set_register(index, 7, 1);
+    }
+    let indices = (0..6).map(|i| src.add_node(i * 10)).collect::<Vec<_>>();
+    src.connect(indices[0], indices[1], 1, 0);
+    src.remove_node(indices[3]);
+    dst.copy_from(src);
+    assert_eq!(dst.len(), 5);
+    assert_eq!(dst.free_count(), 1);
+    assert_eq!(dst.get_register(indices[0], 1), indices[1]);
+    assert_eq!(dst.get_register(indices[1], 0), indices[0]);
+    // the freed slot is reused first, then fresh slots with cleared registers
+    assert_eq!(dst.add_node(1), indices[3]);
+    for i in 7..=12 {
+        assert_eq!(dst.add_node(i), i as u32);
+        assert_eq!(dst.get_register(i as u32, 1), SENTINEL);
+    }
+    for (i, index) in indices.iter().enumerate().filter(|(i, _)| *i != 3) {
+        assert_eq!(*dst.get(*index).get_value(), i as u64 * 10);
+    }
+
+    // copying back overwrites src, which holds fewer entries, with all 12 entries of dst
+    src.copy_from(dst);
+    assert_eq!(src.len(), 12);
+    assert_eq!(src.remaining_capacity(), 4);
+}
+
+#[test]
+fn test_reset() {
+    type Allocator = NodeAllocator<u64, 16, 2>;
+    let mut buf = vec![0u8; std::mem::size_of::<Allocator>()];
+    let allocator = Allocator::load_mut_bytes(buf.as_mut_slice()).unwrap();
+    allocator.initialize();
+    let indices = (0..16).map(|i| allocator.add_node(i)).collect::<Vec<_>>();
+    for w in indices.windows(2) {
+        allocator.connect(w[0], w[1], 1, 0);
+    }
+    allocator.remove_node(indices[5]);
+    allocator.reset();
+    assert!(allocator.is_empty());
+    for (i, index) in indices.iter().enumerate() {
+        assert_eq!(allocator.add_node(i as u64 * 2), *index);
+        assert_eq!(allocator.get_register(*index, 0), SENTINEL);
+        assert_eq!(allocator.get_register(*index, 1), SENTINEL);
+    }
+    assert_eq!(allocator.len(), 16);
+}
+
+#[test]
+fn test_node_eq() {
+    let mut a = Node::<u64, 4>::default();
+    a.set_register(1, 7);
+    a.set_value(42);
+    let mut b = Node::<u64, 4>::default();
+    b.set_value(42);
+    b.set_register(1, 7);
+    assert!(a == b);
+
+    b.set_register(3, 1);
+    assert!(a != b);
+    b.set_register(3, SENTINEL);
+    assert!(a == b);
+    b.set_value(43);
+    assert!(a != b);
+}
+
+#[test]
+fn test_typed_fields() {
+    type Allocator = NodeAllocator<u64, 8, 4>;
+    let mut allocator = Allocator::new();
+    let a = allocator.add_node(1);
+    let b = allocator.add_node(2);
+    allocator.connect_fields(a, b, TreeField::Left, TreeField::Parent);
+    assert_eq!(allocator.get_field(a, TreeField::Left), b);
+    assert_eq!(allocator.get_register(a, TreeField::Left as u32), b);
+    assert_eq!(allocator.get_field(b, TreeField::Parent), a);
+    allocator.set_field(b, 7, TreeField::Value);
+    assert_eq!(allocator.get_register(b, 3), 7);
+    allocator.clear_field(b, TreeField::Value);
+    assert_eq!(allocator.get_field(b, TreeField::Value), SENTINEL);
+    allocator.disconnect_fields(a, b, TreeField::Left, TreeField::Parent);
+    assert_eq!(allocator.get_field(a, TreeField::Left), SENTINEL);
+    assert_eq!(allocator.get_field(b, TreeField::Parent), SENTINEL);
+    assert_eq!(allocator.get_field(SENTINEL, NodeField::Right), SENTINEL);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "out of bounds")]
+fn test_typed_field_out_of_bounds() {
+    let mut allocator = NodeAllocator::<u64, 8, 2>::new();
+    let a = allocator.add_node(1);
+    allocator.get_field(a, TreeField::Parent);
+}
+
+#[test]
+fn test_single_node_allocator() {
+    let mut allocator = NodeAllocator::<u64, 1, 2>::new();
+    assert_eq!(allocator.capacity(), 1);
+    let i = allocator.add_node(7);
+    assert_eq!(i, 1);
+    assert!(allocator.is_full());
+    allocator.remove_node(i);
+    assert_eq!(allocator.add_node(8), i);
+    assert_eq!(*allocator.get(i).get_value(), 8);
+}
+
+#[test]
+#[should_panic(expected = "Buffer is full")]
+fn test_single_node_allocator_overflow() {
+    let mut allocator = NodeAllocator::<u64, 1, 2>::new();
+    allocator.add_node(1);
+    allocator.add_node(2);
 }
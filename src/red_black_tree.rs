@@ -1,13 +1,20 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 use bytemuck::{Pod, Zeroable};
-use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
-use std::{
+use core::{
     cmp::Ordering,
     fmt::Debug,
-    ops::{Index, IndexMut},
-    vec,
+    ops::{Bound, Index, IndexMut, RangeBounds},
 };
+use num_derive::FromPrimitive;
 
+use crate::deque::Deque;
+use crate::error::SokobanError;
 use crate::node_allocator::{
     FromSlice, NodeAllocator, NodeAllocatorMap, OrderedNodeAllocatorMap, TreeField as Field,
     ZeroCopy, SENTINEL,
@@ -16,7 +23,16 @@ use crate::node_allocator::{
 pub const ALIGNMENT: u32 = 8;
 
 // Register aliases
-pub const COLOR: u32 = Field::Value as u32;
+pub const COLOR: Field = Field::Value;
+
+/// Trees up to this size are fully validated after every mutation in debug builds. Validation is
+/// linear in the size of the tree, so checking larger trees on every mutation would make
+/// large debug-mode simulations quadratic.
+#[cfg(debug_assertions)]
+const DEBUG_VALIDATION_MAX_LEN: usize = 1024;
+
+/// Selects the color bit of the COLOR register
+const COLOR_MASK: u32 = 1;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum Color {
@@ -24,10 +40,24 @@ pub enum Color {
     Red = 1,
 }
 
-/// Exploits the fact that LEFT and RIGHT are set to 0 and 1 respectively
 #[inline(always)]
-fn opposite(dir: u32) -> u32 {
-    1 - dir
+fn opposite(dir: Field) -> Field {
+    match dir {
+        Field::Left => Field::Right,
+        Field::Right => Field::Left,
+        _ => panic!("Only Left and Right have an opposite direction"),
+    }
+}
+
+/// Converts a computed direction to a child field. As with LEFT and RIGHT being 0 and 1, 0 is
+/// the left child and any other value is the right child.
+#[inline(always)]
+fn direction(dir: u32) -> Field {
+    if dir == 0 {
+        Field::Left
+    } else {
+        Field::Right
+    }
 }
 
 #[repr(C)]
@@ -63,6 +93,14 @@ impl<
     }
 }
 
+/// Red-black tree whose nodes live in a `NodeAllocator` with 4 registers per node (left, right,
+/// parent and COLOR).
+///
+/// The COLOR register stores the color of a node in its lowest bit and the size of the subtree
+/// rooted at the node in the remaining bits. Earlier versions of this crate stored only the
+/// color, so a tree written by them reads as having a subtree size of 0 everywhere. Such trees
+/// must call `rebuild_subtree_sizes` once before using `select`, `rank`, or any mutation in a
+/// debug build (which validates the sizes).
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct RedBlackTree<
@@ -71,7 +109,9 @@ pub struct RedBlackTree<
     const MAX_SIZE: usize,
 > {
     pub root: u32,
-    _padding: [u32; 3],
+    _padding: u32,
+    /// Incremented on every structural mutation (insertion of a new key or removal)
+    version: u64,
     allocator: NodeAllocator<RBNode<K, V>, MAX_SIZE, 4>,
 }
 
@@ -108,7 +148,8 @@ impl<
         Self::assert_proper_alignment();
         RedBlackTree {
             root: SENTINEL,
-            _padding: [0; 3],
+            _padding: 0,
+            version: 0,
             allocator: NodeAllocator::<RBNode<K, V>, MAX_SIZE, 4>::default(),
         }
     }
@@ -122,7 +163,7 @@ impl<
 {
     fn new_from_slice(slice: &mut [u8]) -> &mut Self {
         Self::assert_proper_alignment();
-        let tree = Self::load_mut_bytes(slice).unwrap();
+        let tree = Self::try_load_mut_bytes(slice).unwrap();
         tree.initialize();
         tree
     }
@@ -165,17 +206,23 @@ impl<
     }
 
     fn size(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     fn len(&self) -> usize {
-        self.allocator.size as usize
+        self.allocator.len()
     }
 
     fn capacity(&self) -> usize {
         MAX_SIZE
     }
 
+    fn clear(&mut self) {
+        self.root = SENTINEL;
+        self.allocator.reset();
+        self.version += 1;
+    }
+
     fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (&K, &V)> + '_> {
         Box::new(self._iter())
     }
@@ -233,8 +280,7 @@ impl<
         let mut s = String::new();
         let mut stack = vec![(self.root, "".to_string(), "".to_string())];
 
-        while !stack.is_empty() {
-            let (node, mut padding, pointer) = stack.pop().unwrap();
+        while let Some((node, mut padding, pointer)) = stack.pop() {
             if node == SENTINEL {
                 continue;
             }
@@ -260,14 +306,15 @@ impl<
             stack.push((self.get_right(node), padding.clone(), right_pointer));
             stack.push((self.get_left(node), padding.clone(), left_pointer));
         }
-        println!("{}", s);
+        std_println!("{}", s);
     }
 
     fn assert_proper_alignment() {
         // TODO is this a sufficient coverage of the edge cases?
-        assert!(std::mem::size_of::<V>() % std::mem::align_of::<K>() == 0);
-        assert!(std::mem::size_of::<RBNode<K, V>>() % std::mem::align_of::<RBNode<K, V>>() == 0);
-        assert!(std::mem::size_of::<RBNode<K, V>>() % 8_usize == 0);
+        assert!(core::mem::size_of::<V>().is_multiple_of(core::mem::align_of::<K>()));
+        assert!(core::mem::size_of::<RBNode<K, V>>()
+            .is_multiple_of(core::mem::align_of::<RBNode<K, V>>()));
+        assert!(core::mem::size_of::<RBNode<K, V>>().is_multiple_of(8_usize));
     }
 
     pub fn is_valid_red_black_tree(&self) -> bool {
@@ -276,15 +323,27 @@ impl<
         }
         // The root must be black
         if self.is_red(self.root) {
-            println!("Invalid Red-Black Tree: Root is red");
+            std_println!("Invalid Red-Black Tree: Root is red");
             return false;
         }
 
         let mut stack = vec![(self.root, 0)];
         let mut black_count = vec![];
 
-        while !stack.is_empty() {
-            let (node_index, mut count) = stack.pop().unwrap();
+        while let Some((node_index, mut count)) = stack.pop() {
+            // Every node must track the size of its subtree
+            let expected_size = 1
+                + self.subtree_size(self.get_left(node_index))
+                + self.subtree_size(self.get_right(node_index));
+            if self.subtree_size(node_index) != expected_size {
+                std_println!(
+                    "Invalid Red-Black Tree: Node (key: {:?}) has subtree size {}, expected {}",
+                    self.get_node(node_index).key,
+                    self.subtree_size(node_index),
+                    expected_size
+                );
+                return false;
+            }
             count += self.is_black(node_index) as u32;
             if self.is_leaf(node_index) {
                 black_count.push(count);
@@ -296,7 +355,7 @@ impl<
                 }
                 // Red nodes cannot have red children
                 if self.is_red(node_index) && self.is_red(child) {
-                    println!(
+                    std_println!(
                         "Invalid Red-Black Tree: Red node (key: {:?}) has red child",
                         self.get_node(node_index).key
                     );
@@ -308,11 +367,86 @@ impl<
         // All paths from root to leaf must have the same number of black nodes
         let balanced = black_count.iter().all(|&x| x == black_count[0]);
         if !balanced {
-            println!("Invalid Red-Black Tree: All paths must have the same number of black nodes",);
+            std_println!(
+                "Invalid Red-Black Tree: All paths must have the same number of black nodes",
+            );
         }
         balanced
     }
 
+    /// Returns true if every child node points back to its parent and the root has no parent
+    pub fn verify_parent_pointers(&self) -> bool {
+        if self.root == SENTINEL {
+            return true;
+        }
+        if self.get_parent(self.root) != SENTINEL {
+            std_println!("Invalid Red-Black Tree: Root has a parent");
+            return false;
+        }
+        let mut stack = vec![self.root];
+        while let Some(node_index) = stack.pop() {
+            for child in [self.get_left(node_index), self.get_right(node_index)] {
+                if child == SENTINEL {
+                    continue;
+                }
+                if self.get_parent(child) != node_index {
+                    std_println!(
+                        "Invalid Red-Black Tree: Child (key: {:?}) of node (key: {:?}) has the wrong parent",
+                        self.get_node(child).key,
+                        self.get_node(node_index).key
+                    );
+                    return false;
+                }
+                stack.push(child);
+            }
+        }
+        true
+    }
+
+    /// Validates the whole tree after a mutation of `key`. This is compiled out of release builds.
+    #[cfg(debug_assertions)]
+    fn _debug_check_invariants(&self, key: &K) {
+        if self.len() > DEBUG_VALIDATION_MAX_LEN {
+            return;
+        }
+        assert!(
+            self.is_valid_red_black_tree() && self.verify_parent_pointers(),
+            "Red-Black Tree invariants violated after mutating key {:?}",
+            key
+        );
+    }
+
+    /// Checks that comparing `key` with `curr_key` while descending to insert `key` agrees with
+    /// the branches already taken at its `lower` and `upper` ancestors. The tree only works with
+    /// a total order, and this catches `Ord` implementations that are not one before they
+    /// corrupt the tree. This is compiled out of release builds.
+    #[cfg(debug_assertions)]
+    fn _debug_check_descent(
+        key: &K,
+        curr_key: &K,
+        ordering: Ordering,
+        lower: Option<K>,
+        upper: Option<K>,
+    ) {
+        assert!(
+            curr_key.cmp(key) == ordering.reverse(),
+            "Inconsistent Ord: {:?} and {:?} do not compare antisymmetrically",
+            key,
+            curr_key
+        );
+        for (bound, expected) in [(lower, Ordering::Greater), (upper, Ordering::Less)] {
+            if let Some(bound) = bound {
+                assert!(
+                    key.cmp(&bound) == expected && curr_key.cmp(&bound) == expected,
+                    "Inconsistent Ord: {:?} and {:?} are not on the same side of ancestor {:?}",
+                    key,
+                    curr_key,
+                    bound
+                );
+            }
+        }
+    }
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -322,6 +456,572 @@ impl<
         self.allocator.initialize();
     }
 
+    /// Returns the index of the node for `key`, inserting it with `default` if it is missing.
+    /// Unlike `insert`, the value of an existing key is never overwritten. Returns `None` if the
+    /// key is missing and the tree is full.
+    pub fn intern(&mut self, key: K, default: V) -> Option<u32> {
+        self._get_or_insert_with(key, || default)
+            .map(|(node_index, _)| node_index)
+    }
+
+    /// Returns a mutable reference to the value for `key`, inserting the result of `f` if the key
+    /// is missing. The tree is only descended once. Returns `None` if the key is missing and the
+    /// tree is full.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<&mut V> {
+        let (node_index, _) = self._get_or_insert_with(key, f)?;
+        Some(&mut self.get_node_mut(node_index).value)
+    }
+
+    /// Makes `self` a copy of `other` without reinitializing the buffer. Only the part of
+    /// `other`'s node array that is in use is copied.
+    pub fn copy_from(&mut self, other: &Self) {
+        self.root = other.root;
+        self.allocator.copy_from(&other.allocator);
+        self.version = self.version.max(other.version) + 1;
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.allocator.is_full()
+    }
+
+    /// Returns the number of new keys that can still be inserted
+    pub fn remaining_capacity(&self) -> usize {
+        self.allocator.remaining_capacity()
+    }
+
+    /// Like `insert`, but a tree that is too full to hold a new key is reported as
+    /// `SokobanError::Full`. Overwriting an existing key always succeeds, even when the tree is
+    /// full. The crate-wide `SokobanError` is used rather than a tree-specific error type so that
+    /// capacity failures look the same across containers (see `try_insert_batch` and `union`).
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<u32, SokobanError> {
+        self._insert(key, value).ok_or(SokobanError::Full)
+    }
+
+    /// Inserts `key` only if it is not already in the tree, returning the index of the new node.
+    /// If the key is present, the tree is left unchanged and the stored key and value are
+    /// returned as the error. If the tree is full, the arguments are handed back instead.
+    #[must_use = "an existing key is not overwritten, so the error should be checked"]
+    pub fn insert_new(&mut self, key: K, value: V) -> Result<u32, (K, V)> {
+        match self._get_or_insert_with(key, || value) {
+            Some((node_index, true)) => Ok(node_index),
+            Some((node_index, false)) => {
+                let node = self.get_node(node_index);
+                Err((node.key, node.value))
+            }
+            None => Err((key, value)),
+        }
+    }
+
+    /// Inserts all of `pairs` or none of them. The number of keys that are not already in the
+    /// tree is counted first, and if the tree cannot hold all of them `SokobanError::Full` is
+    /// returned without modifying the tree. On success, returns the number of new keys inserted.
+    /// Keys that are already present (or repeated in `pairs`) are overwritten in order.
+    pub fn try_insert_batch(&mut self, pairs: &[(K, V)]) -> Result<usize, SokobanError> {
+        let mut new_keys = pairs
+            .iter()
+            .filter(|(k, _)| self.get_addr(k) == SENTINEL)
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        new_keys.sort();
+        new_keys.dedup();
+        if self.allocator.remaining_capacity() < new_keys.len() {
+            return Err(SokobanError::Full);
+        }
+        for (k, v) in pairs {
+            self._insert(*k, *v).ok_or(SokobanError::Full)?;
+        }
+        Ok(new_keys.len())
+    }
+
+    /// Builds a tree with the same keys as `self` in `buf`, with each value transformed by `f`.
+    /// This is useful for migrating the value type of an existing tree. `buf` must be at least
+    /// `size_of::<RedBlackTree<K, V2, MAX_SIZE>>()` bytes and properly aligned.
+    pub fn map_values<'a, V2, F>(
+        &self,
+        buf: &'a mut [u8],
+        mut f: F,
+    ) -> &'a mut RedBlackTree<K, V2, MAX_SIZE>
+    where
+        V2: Default + Copy + Clone + Pod + Zeroable,
+        F: FnMut(&V) -> V2,
+    {
+        let tree = RedBlackTree::<K, V2, MAX_SIZE>::new_from_slice(buf);
+        for (k, v) in self.iter() {
+            tree.insert(*k, f(v));
+        }
+        tree
+    }
+
+    /// Returns the stored key and value for `key`. The stored key can differ from `key` in any
+    /// fields that are not used by its `Ord` implementation.
+    pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+        match self.get_addr(key) {
+            SENTINEL => None,
+            node_index => {
+                let node = self.get_node(node_index);
+                Some((&node.key, &node.value))
+            }
+        }
+    }
+
+    /// Alias for `contains`, matching the naming of `std::collections::BTreeMap`
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.contains(key)
+    }
+
+    /// Returns true if every key in `keys` is in the tree. When `keys` is sorted in ascending
+    /// order, this is answered with a single in-order walk of the tree; otherwise each key is
+    /// looked up individually.
+    pub fn contains_all(&self, keys: &[K]) -> bool {
+        if !keys.windows(2).all(|w| w[0] <= w[1]) {
+            return keys.iter().all(|k| self.get_addr(k) != SENTINEL);
+        }
+        let mut tree_keys = self.iter().map(|(k, _)| k).peekable();
+        for key in keys {
+            while tree_keys.next_if(|k| *k < key).is_some() {}
+            if tree_keys.peek() != Some(&key) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns true if any key in `keys` is in the tree.
+    pub fn contains_any(&self, keys: &[K]) -> bool {
+        keys.iter().any(|k| self.get_addr(k) != SENTINEL)
+    }
+
+    /// Returns the half-open interval of ranks `[start, end)` covered by the keys in `range`,
+    /// where the rank of a key is the number of keys in the tree that are less than it. An
+    /// empty or inverted range yields `start == end`.
+    pub fn rank_range<R: RangeBounds<K>>(&self, range: R) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(k) => self.rank(k),
+            Bound::Excluded(k) => self.rank(k) + self.contains(k) as usize,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(k) => self.rank(k) + self.contains(k) as usize,
+            Bound::Excluded(k) => self.rank(k),
+            Bound::Unbounded => self.len(),
+        };
+        (start, end.max(start))
+    }
+
+    /// Returns the `k`-th smallest entry of the tree (0-indexed), or `None` if `k >= len()`
+    pub fn select(&self, mut k: usize) -> Option<(&K, &V)> {
+        let mut node_index = self.root;
+        while node_index != SENTINEL {
+            let left = self.get_left(node_index);
+            let left_size = self.subtree_size(left);
+            match k.cmp(&left_size) {
+                Ordering::Less => node_index = left,
+                Ordering::Equal => {
+                    let node = self.get_node(node_index);
+                    return Some((&node.key, &node.value));
+                }
+                Ordering::Greater => {
+                    k -= left_size + 1;
+                    node_index = self.get_right(node_index);
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the number of keys in the tree that are strictly less than `key`
+    pub fn rank(&self, key: &K) -> usize {
+        let mut rank = 0;
+        let mut node_index = self.root;
+        while node_index != SENTINEL {
+            let left = self.get_left(node_index);
+            match key.cmp(&self.get_node(node_index).key) {
+                Ordering::Less => node_index = left,
+                Ordering::Equal => return rank + self.subtree_size(left),
+                Ordering::Greater => {
+                    rank += self.subtree_size(left) + 1;
+                    node_index = self.get_right(node_index);
+                }
+            }
+        }
+        rank
+    }
+
+    /// Returns an in-order iterator over the tree. Unlike `NodeAllocatorMap::iter`, the iterator
+    /// is not boxed, so it is `Send` whenever `K` and `V` are `Sync`.
+    pub fn iter(&self) -> RedBlackTreeIterator<'_, K, V, MAX_SIZE> {
+        self._iter()
+    }
+
+    /// Returns an in-order iterator over the tree with mutable access to the values. Like `iter`,
+    /// this returns the concrete iterator instead of the boxed one from `NodeAllocatorMap`.
+    pub fn iter_mut(&mut self) -> RedBlackTreeIteratorMut<'_, K, V, MAX_SIZE> {
+        self._iter_mut()
+    }
+
+    /// Returns an in-order iterator over the entries with keys greater than or equal to `key`.
+    /// The forward stack is seeded with the path to the ceiling of `key`, so the iterator starts
+    /// there without visiting the smaller keys. Iterating from the back stops at the same ceiling.
+    pub fn iter_from(&self, key: &K) -> RedBlackTreeIterator<'_, K, V, MAX_SIZE> {
+        let mut iter = self._iter();
+        let mut node_index = self.root;
+        while node_index != SENTINEL {
+            if self.get_node(node_index).key >= *key {
+                iter.fwd_stack.push(node_index);
+                node_index = self.get_left(node_index);
+            } else {
+                node_index = self.get_right(node_index);
+            }
+        }
+        iter.fwd_ptr = SENTINEL;
+        iter.remaining = self.len() - self.rank(key);
+        iter
+    }
+
+    /// Returns the entry for `key` for in-place lookup, insertion, and modification.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, MAX_SIZE> {
+        let node_index = self.get_addr(&key);
+        if node_index == SENTINEL {
+            Entry::Vacant(VacantEntry { tree: self, key })
+        } else {
+            Entry::Occupied(OccupiedEntry {
+                tree: self,
+                node_index,
+            })
+        }
+    }
+
+    /// Copies each entry into `yes` if `pred` returns true for it, and into `no` otherwise.
+    /// Entries are visited in order. If either target fills up, `SokobanError::Full` is
+    /// returned and the remaining entries are not copied.
+    pub fn partition<F: FnMut(&K, &V) -> bool>(
+        &self,
+        yes: &mut RedBlackTree<K, V, MAX_SIZE>,
+        no: &mut RedBlackTree<K, V, MAX_SIZE>,
+        mut pred: F,
+    ) -> Result<(), SokobanError> {
+        for (k, v) in self.iter() {
+            let target = if pred(k, v) { &mut *yes } else { &mut *no };
+            target._insert(*k, *v).ok_or(SokobanError::Full)?;
+        }
+        Ok(())
+    }
+
+    /// Splits the tree at `key`. All entries less than `key` remain in `self`, and a new tree
+    /// holding the entries greater than or equal to `key` is returned. Note that the returned
+    /// tree is constructed by value, so this is only suitable for trees that fit on the stack.
+    pub fn split_off(&mut self, key: &K) -> Self {
+        let mut other = Self::new();
+        let moved = self
+            .iter()
+            .rev()
+            .take_while(|(k, _)| *k >= key)
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>();
+        for (k, v) in moved.into_iter().rev() {
+            self._remove(&k);
+            other._insert(k, v);
+        }
+        other
+    }
+
+    /// Retains only the entries for which `f` returns true, visiting entries in order.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        for node_index in self._filter_addrs(|k, v| !f(k, v)) {
+            self._remove_tree_node(node_index);
+        }
+    }
+
+    /// Removes the entries for which `f` returns true, returning them in key order.
+    pub fn drain_filter<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) -> Vec<(K, V)> {
+        let node_indices = self._filter_addrs(f);
+        let mut drained = Vec::with_capacity(node_indices.len());
+        for node_index in node_indices {
+            let RBNode { key, value } = *self.get_node(node_index);
+            self._remove_tree_node(node_index);
+            drained.push((key, value));
+        }
+        drained
+    }
+
+    /// Returns the addresses of the nodes for which `f` returns true, in key order. The addresses
+    /// remain valid while the other nodes are removed because `_remove_tree_node` relinks nodes
+    /// instead of moving entries between slots.
+    fn _filter_addrs<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) -> Vec<u32> {
+        let mut node_indices = vec![];
+        let mut node_index = self._find_min(self.root);
+        while node_index != SENTINEL {
+            let node = self.get_node_mut(node_index);
+            if f(&node.key, &mut node.value) {
+                node_indices.push(node_index);
+            }
+            node_index = self.successor(node_index);
+        }
+        node_indices
+    }
+
+    /// Pushes the entries in `range` onto the back of `dst` in key order, stopping early if `dst`
+    /// fills up. Entries are copied as `RBNode`s because tuples are not `Pod`. The walk starts at
+    /// the first node in `range` and follows `successor`, so it takes O(log n + k) time for k
+    /// copied entries. Returns the number of entries copied.
+    pub fn copy_range_into_deque<R: RangeBounds<K>, const DEQUE_SIZE: usize>(
+        &self,
+        range: R,
+        dst: &mut Deque<RBNode<K, V>, DEQUE_SIZE>,
+    ) -> usize {
+        let mut copied = 0;
+        for node_index in self.addr_range(range) {
+            if dst.is_full() {
+                break;
+            }
+            dst.push_back(*self.get_node(node_index));
+            copied += 1;
+        }
+        copied
+    }
+
+    /// Removes and returns the entry with the smallest key
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.root == SENTINEL {
+            return None;
+        }
+        self._pop_node(self._find_min(self.root))
+    }
+
+    /// Removes and returns the entry with the largest key
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        if self.root == SENTINEL {
+            return None;
+        }
+        self._pop_node(self._find_max(self.root))
+    }
+
+    fn _pop_node(&mut self, node_index: u32) -> Option<(K, V)> {
+        let RBNode { key, value } = *self.get_node(node_index);
+        self._remove_tree_node(node_index);
+        Some((key, value))
+    }
+
+    /// Returns the address of the node with the next largest key, or `SENTINEL` if `node` holds
+    /// the largest key
+    pub fn successor(&self, node: u32) -> u32 {
+        self._neighbor(node, Field::Right)
+    }
+
+    /// Returns the address of the node with the next smallest key, or `SENTINEL` if `node` holds
+    /// the smallest key
+    pub fn predecessor(&self, node: u32) -> u32 {
+        self._neighbor(node, Field::Left)
+    }
+
+    /// Returns the in-order neighbor of `node` in direction `dir`
+    fn _neighbor(&self, mut node: u32, dir: Field) -> u32 {
+        if node == SENTINEL {
+            return SENTINEL;
+        }
+        let child = self._child(node, dir);
+        if child != SENTINEL {
+            // The closest node in `dir` is the extreme node of the child subtree in the
+            // opposite direction
+            let mut next = child;
+            while self._child(next, opposite(dir)) != SENTINEL {
+                next = self._child(next, opposite(dir));
+            }
+            return next;
+        }
+        let mut parent = self.get_parent(node);
+        while parent != SENTINEL && self._child(parent, dir) == node {
+            node = parent;
+            parent = self.get_parent(node);
+        }
+        parent
+    }
+
+    /// Returns an in-order iterator over the keys of the tree
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> {
+        self._iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values of the tree, in key order
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> {
+        self._iter().map(|(_, v)| v)
+    }
+
+    /// Returns a mutable iterator over the values of the tree, in key order
+    pub fn values_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut V> {
+        self._iter_mut().map(|(_, v)| v)
+    }
+
+    /// Returns the addresses of the nodes with keys in `range`, in key order
+    pub fn addr_range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = u32> + '_ {
+        let mut node = self._lower_bound_addr(range.start_bound());
+        let end = range.end_bound().cloned();
+        core::iter::from_fn(move || {
+            if node == SENTINEL {
+                return None;
+            }
+            let key = &self.get_node(node).key;
+            let in_range = match &end {
+                Bound::Included(k) => key <= k,
+                Bound::Excluded(k) => key < k,
+                Bound::Unbounded => true,
+            };
+            if !in_range {
+                node = SENTINEL;
+                return None;
+            }
+            let current = node;
+            node = self.successor(node);
+            Some(current)
+        })
+    }
+
+    /// Returns the keys in `range`, in order
+    pub fn keys_range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = &K> + '_ {
+        self.addr_range(range)
+            .map(move |node_index| &self.get_node(node_index).key)
+    }
+
+    /// Returns the address of the node with the smallest key satisfying the lower bound `start`,
+    /// or `SENTINEL` if there is no such node
+    fn _lower_bound_addr(&self, start: Bound<&K>) -> u32 {
+        let mut node = self.root;
+        let mut candidate = SENTINEL;
+        while node != SENTINEL {
+            let key = &self.get_node(node).key;
+            let satisfies = match start {
+                Bound::Included(k) => key >= k,
+                Bound::Excluded(k) => key > k,
+                Bound::Unbounded => true,
+            };
+            if satisfies {
+                candidate = node;
+                node = self.get_left(node);
+            } else {
+                node = self.get_right(node);
+            }
+        }
+        candidate
+    }
+
+    /// Initializes a tree in `slice` from `items`, which must be sorted by key in strictly
+    /// increasing order. The tree is built bottom-up in O(N) without any rotations: the middle
+    /// element of each range becomes the root of its subtree, and the nodes on the deepest level
+    /// (if it is not the root) are colored red.
+    ///
+    /// Panics if `items` is not strictly increasing or does not fit in the tree, in release
+    /// builds too, since an unsorted tree would silently break every lookup.
+    pub fn from_sorted_slice<'a>(slice: &'a mut [u8], items: &[(K, V)]) -> &'a mut Self {
+        assert!(
+            items.windows(2).all(|w| w[0].0 < w[1].0),
+            "Items must be sorted by key with no duplicates"
+        );
+        assert!(items.len() <= MAX_SIZE, "Too many items for the tree");
+        let tree = Self::new_from_slice(slice);
+        if !items.is_empty() {
+            let max_depth = items.len().ilog2();
+            tree.root = tree._build_sorted(items, 0, max_depth);
+            tree.version += 1;
+        }
+        tree
+    }
+
+    /// Replaces the contents of `out` with the union of `self` and `other`, keeping the value from
+    /// `self` for keys that are in both trees. The in-order traversals are merged in O(n + m) and
+    /// `out` is rebuilt bottom-up rather than by repeated insertion. Returns the number of entries
+    /// in `out`, or `SokobanError::Full` (leaving `out` unchanged) if the union does not fit.
+    pub fn union(&self, other: &Self, out: &mut Self) -> Result<usize, SokobanError> {
+        let mut merged = Vec::with_capacity(self.len().max(other.len()));
+        let mut lhs = self.iter().peekable();
+        let mut rhs = other.iter().peekable();
+        loop {
+            let next = match (lhs.peek(), rhs.peek()) {
+                (Some((lk, _)), Some((rk, _))) => match lk.cmp(rk) {
+                    Ordering::Less => lhs.next(),
+                    Ordering::Greater => rhs.next(),
+                    Ordering::Equal => {
+                        rhs.next();
+                        lhs.next()
+                    }
+                },
+                (Some(_), None) => lhs.next(),
+                (None, Some(_)) => rhs.next(),
+                (None, None) => break,
+            };
+            if merged.len() == MAX_SIZE {
+                return Err(SokobanError::Full);
+            }
+            merged.extend(next.map(|(k, v)| (*k, *v)));
+        }
+        out._replace_with_sorted(&merged);
+        Ok(merged.len())
+    }
+
+    /// Replaces the contents of `out` with the keys that are in both `self` and `other`, with the
+    /// values from `self`. Like `union`, this is a linear merge followed by a bottom-up rebuild.
+    /// Returns the number of entries in `out`. The intersection is never larger than `self`, so
+    /// this cannot fail for trees of the same type, but the `Result` mirrors `union`.
+    pub fn intersection(&self, other: &Self, out: &mut Self) -> Result<usize, SokobanError> {
+        let mut merged = Vec::with_capacity(self.len().min(other.len()));
+        let mut lhs = self.iter().peekable();
+        let mut rhs = other.iter().peekable();
+        while let (Some((lk, lv)), Some((rk, _))) = (lhs.peek(), rhs.peek()) {
+            match lk.cmp(rk) {
+                Ordering::Less => {
+                    lhs.next();
+                }
+                Ordering::Greater => {
+                    rhs.next();
+                }
+                Ordering::Equal => {
+                    merged.push((**lk, **lv));
+                    lhs.next();
+                    rhs.next();
+                }
+            }
+        }
+        if merged.len() > MAX_SIZE {
+            return Err(SokobanError::Full);
+        }
+        out._replace_with_sorted(&merged);
+        Ok(merged.len())
+    }
+
+    /// Clears the tree and rebuilds it from `items`, which must be sorted by key with no
+    /// duplicates and fit in the tree
+    fn _replace_with_sorted(&mut self, items: &[(K, V)]) {
+        self.clear();
+        if !items.is_empty() {
+            self.root = self._build_sorted(items, 0, items.len().ilog2());
+        }
+    }
+
+    fn _build_sorted(&mut self, items: &[(K, V)], depth: u32, max_depth: u32) -> u32 {
+        if items.is_empty() {
+            return SENTINEL;
+        }
+        let mid = items.len() / 2;
+        let (key, value) = items[mid];
+        let node_index = self.allocator.add_node(RBNode::new(key, value));
+        let left = self._build_sorted(&items[..mid], depth + 1, max_depth);
+        let right = self._build_sorted(&items[mid + 1..], depth + 1, max_depth);
+        self._connect(node_index, left, Field::Left);
+        self._connect(node_index, right, Field::Right);
+        self._set_subtree_size(node_index, items.len());
+        if depth == max_depth && depth > 0 {
+            self._color_red(node_index);
+        } else {
+            self._color_black(node_index);
+        }
+        node_index
+    }
+
+    /// Returns a change token that is bumped whenever a key is added to or removed from the tree.
+    /// Updating the value of an existing key does not change the version.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     pub fn get_node(&self, node: u32) -> &RBNode<K, V> {
         self.allocator.get(node).get_value()
     }
@@ -332,30 +1032,79 @@ impl<
 
     #[inline(always)]
     fn _color_red(&mut self, node: u32) {
-        if node != SENTINEL {
-            self.allocator.set_register(node, Color::Red as u32, COLOR);
-        }
+        self._color_node(node, Color::Red as u32);
     }
 
     #[inline(always)]
     fn _color_black(&mut self, node: u32) {
-        self.allocator
-            .set_register(node, Color::Black as u32, COLOR);
+        self._color_node(node, Color::Black as u32);
     }
 
     #[inline(always)]
     fn _color_node(&mut self, node: u32, color: u32) {
-        self.allocator.set_register(node, color, COLOR);
+        let register = self.allocator.get_field(node, COLOR);
+        self.allocator
+            .set_field(node, (register & !COLOR_MASK) | color, COLOR);
     }
 
     #[inline(always)]
     pub fn is_red(&self, node: u32) -> bool {
-        self.allocator.get_register(node, COLOR) == Color::Red as u32
+        self.get_color(node) == Color::Red as u32
     }
 
     #[inline(always)]
     pub fn is_black(&self, node: u32) -> bool {
-        self.allocator.get_register(node, COLOR) == Color::Black as u32
+        self.get_color(node) == Color::Black as u32
+    }
+
+    /// Returns the number of nodes in the subtree rooted at `node` (0 for `SENTINEL`)
+    #[inline(always)]
+    pub fn subtree_size(&self, node: u32) -> usize {
+        (self.allocator.get_field(node, COLOR) >> 1) as usize
+    }
+
+    #[inline(always)]
+    fn _set_subtree_size(&mut self, node: u32, size: usize) {
+        let color = self.get_color(node);
+        self.allocator
+            .set_field(node, ((size as u32) << 1) | color, COLOR);
+    }
+
+    #[inline(always)]
+    fn _update_subtree_size(&mut self, node: u32) {
+        let size =
+            1 + self.subtree_size(self.get_left(node)) + self.subtree_size(self.get_right(node));
+        self._set_subtree_size(node, size);
+    }
+
+    /// Recomputes the subtree size of every node in O(n), children before parents. Trees written
+    /// before subtree sizes were stored in the COLOR register have a size of 0 in every node, so
+    /// `select`, `rank`, `rank_range` and `is_valid_red_black_tree` give wrong answers for them
+    /// until this has been called once. Colors and links are not modified.
+    pub fn rebuild_subtree_sizes(&mut self) {
+        let mut preorder = Vec::with_capacity(self.len());
+        let mut stack = vec![self.root];
+        while let Some(node) = stack.pop() {
+            if node == SENTINEL {
+                continue;
+            }
+            preorder.push(node);
+            stack.push(self.get_left(node));
+            stack.push(self.get_right(node));
+        }
+        // Every node appears after its parent in `preorder`, so walking it backwards visits both
+        // children of a node before the node itself
+        for node in preorder.into_iter().rev() {
+            self._update_subtree_size(node);
+        }
+    }
+
+    /// Recomputes the subtree sizes of `node` and all of its ancestors
+    fn _update_ancestor_sizes(&mut self, mut node: u32) {
+        while node != SENTINEL {
+            self._update_subtree_size(node);
+            node = self.get_parent(node);
+        }
     }
 
     #[inline(always)]
@@ -363,6 +1112,11 @@ impl<
         self.allocator.get_register(node, dir)
     }
 
+    #[inline(always)]
+    fn _child(&self, node: u32, dir: Field) -> u32 {
+        self.allocator.get_field(node, dir)
+    }
+
     #[inline(always)]
     pub fn is_leaf(&self, node: u32) -> bool {
         self.get_left(node) == SENTINEL && self.get_right(node) == SENTINEL
@@ -374,31 +1128,27 @@ impl<
     }
 
     pub fn get_dir(&self, node: u32, dir: u32) -> u32 {
-        if dir == Field::Left as u32 {
-            self.get_left(node)
-        } else {
-            self.get_right(node)
-        }
+        self._child(node, direction(dir))
     }
 
     #[inline(always)]
     pub fn get_left(&self, node: u32) -> u32 {
-        self.allocator.get_register(node, Field::Left as u32)
+        self.allocator.get_field(node, Field::Left)
     }
 
     #[inline(always)]
     pub fn get_right(&self, node: u32) -> u32 {
-        self.allocator.get_register(node, Field::Right as u32)
+        self.allocator.get_field(node, Field::Right)
     }
 
     #[inline(always)]
     pub fn get_color(&self, node: u32) -> u32 {
-        self.allocator.get_register(node, COLOR)
+        self.allocator.get_field(node, COLOR) & COLOR_MASK
     }
 
     #[inline(always)]
     pub fn get_parent(&self, node: u32) -> u32 {
-        self.allocator.get_register(node, Field::Parent as u32)
+        self.allocator.get_field(node, Field::Parent)
     }
 
     pub fn remove_root(&mut self) -> Option<RBNode<K, V>> {
@@ -407,7 +1157,7 @@ impl<
             None
         } else {
             // Otherwise copy out and remove tree node
-            let root_node = self.get_node(self.root).clone();
+            let root_node = *self.get_node(self.root);
             self._remove_tree_node(self.root);
             Some(root_node)
         }
@@ -415,46 +1165,43 @@ impl<
 
     fn _remove_allocator_node(&mut self, node: u32) {
         // Clear all registers
-        self.allocator.clear_register(node, Field::Parent as u32);
-        self.allocator.clear_register(node, COLOR);
-        self.allocator.clear_register(node, Field::Left as u32);
-        self.allocator.clear_register(node, Field::Right as u32);
+        self.allocator.clear_field(node, Field::Parent);
+        self.allocator.clear_field(node, COLOR);
+        self.allocator.clear_field(node, Field::Left);
+        self.allocator.clear_field(node, Field::Right);
         // Add free slot to the free list
         self.allocator.remove_node(node);
     }
 
     #[inline(always)]
-    fn _connect(&mut self, parent: u32, child: u32, dir: u32) {
+    fn _connect(&mut self, parent: u32, child: u32, dir: Field) {
         self.allocator
-            .connect(parent, child, dir, Field::Parent as u32);
+            .connect_fields(parent, child, dir, Field::Parent);
     }
 
     #[inline(always)]
-    fn _child_dir(&self, parent: u32, child: u32) -> u32 {
+    fn _child_dir(&self, parent: u32, child: u32) -> Field {
         let left = self.get_left(parent);
         let right = self.get_right(parent);
         if child == left {
-            Field::Left as u32
+            Field::Left
         } else if child == right {
-            Field::Right as u32
+            Field::Right
         } else {
             panic!("Nodes are not connected");
         }
     }
 
-    fn _rotate_dir(&mut self, parent_index: u32, dir: u32) -> Option<u32> {
+    fn _rotate_dir(&mut self, parent_index: u32, dir: Field) -> Option<u32> {
         let grandparent_index = self.get_parent(parent_index);
-        if !matches!(
-            FromPrimitive::from_u32(dir),
-            Some(Field::Left) | Some(Field::Right),
-        ) {
+        if !matches!(dir, Field::Left | Field::Right) {
             return None;
         }
-        let sibling_index = self.get_child(parent_index, opposite(dir));
+        let sibling_index = self._child(parent_index, opposite(dir));
         if sibling_index == SENTINEL {
             return None;
         }
-        let child_index = self.get_child(sibling_index, dir);
+        let child_index = self._child(sibling_index, dir);
         self._connect(sibling_index, parent_index, dir);
         self._connect(parent_index, child_index, opposite(dir));
         if grandparent_index != SENTINEL {
@@ -464,44 +1211,74 @@ impl<
                 self._child_dir(grandparent_index, parent_index),
             );
         } else {
-            self.allocator
-                .clear_register(sibling_index, Field::Parent as u32);
+            self.allocator.clear_field(sibling_index, Field::Parent);
             self.root = sibling_index;
         }
+        // The parent is now a child of the sibling, so its size must be fixed first
+        self._update_subtree_size(parent_index);
+        self._update_subtree_size(sibling_index);
         Some(sibling_index)
     }
 
     fn _insert(&mut self, key: K, value: V) -> Option<u32> {
+        let (node_index, inserted) = self._get_or_insert_with(key, || value)?;
+        if !inserted {
+            self.get_node_mut(node_index).value = value;
+        }
+        Some(node_index)
+    }
+
+    /// Descends the tree once, returning the index of the node matching `key` if it exists.
+    /// Otherwise a new node is created with the value produced by `f`. The boolean flag
+    /// indicates whether a new node was inserted. Returns `None` if the key is missing and
+    /// the tree is full.
+    fn _get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<(u32, bool)> {
         let mut parent_node_index = self.root;
-        let new_node = RBNode::<K, V>::new(key, value);
         if parent_node_index == SENTINEL {
-            let node_index = self.allocator.add_node(new_node);
+            let node_index = self.allocator.add_node(RBNode::<K, V>::new(key, f()));
+            self._set_subtree_size(node_index, 1);
             self.root = node_index;
-            return Some(node_index);
+            self.version += 1;
+            return Some((node_index, true));
         }
+        // Keys of the closest ancestors that `key` was found to be greater and less than
+        #[cfg(debug_assertions)]
+        let (mut lower, mut upper) = (None, None);
         loop {
             let curr_key = self.get_node(parent_node_index).key;
-            let (target, dir) = match key.cmp(&curr_key) {
-                Ordering::Less => (self.get_left(parent_node_index), Field::Left as u32),
-                Ordering::Greater => (self.get_right(parent_node_index), Field::Right as u32),
-                Ordering::Equal => {
-                    self.get_node_mut(parent_node_index).value = value;
-                    return Some(parent_node_index);
+            let ordering = key.cmp(&curr_key);
+            #[cfg(debug_assertions)]
+            {
+                Self::_debug_check_descent(&key, &curr_key, ordering, lower, upper);
+                match ordering {
+                    Ordering::Less => upper = Some(curr_key),
+                    Ordering::Greater => lower = Some(curr_key),
+                    Ordering::Equal => {}
                 }
+            }
+            let (target, dir) = match ordering {
+                Ordering::Less => (self.get_left(parent_node_index), Field::Left),
+                Ordering::Greater => (self.get_right(parent_node_index), Field::Right),
+                Ordering::Equal => return Some((parent_node_index, false)),
             };
             if target == SENTINEL {
-                if self.len() >= self.capacity() {
+                if self.allocator.is_full() {
                     return None;
                 }
-                let node_index = self.allocator.add_node(new_node);
+                let node_index = self.allocator.add_node(RBNode::<K, V>::new(key, f()));
+                self.version += 1;
+                self._set_subtree_size(node_index, 1);
                 self._color_red(node_index);
                 self._connect(parent_node_index, node_index, dir);
+                self._update_ancestor_sizes(parent_node_index);
                 let grandparent = self.get_parent(parent_node_index);
                 // This is only false when the parent is the root
                 if grandparent != SENTINEL {
                     self._fix_insert(node_index);
                 }
-                return Some(node_index);
+                #[cfg(debug_assertions)]
+                self._debug_check_invariants(&key);
+                return Some((node_index, true));
             }
             parent_node_index = target
         }
@@ -516,7 +1293,7 @@ impl<
                 break;
             }
             let dir = self._child_dir(grandparent, parent);
-            let uncle = self.get_child(grandparent, opposite(dir));
+            let uncle = self._child(grandparent, opposite(dir));
             if self.is_red(uncle) {
                 self._color_black(uncle);
                 self._color_black(parent);
@@ -534,12 +1311,12 @@ impl<
                 self._rotate_dir(grandparent, opposite(dir));
             }
         }
-        self._color_black(self.root as u32);
+        self._color_black(self.root);
         Some(())
     }
 
     fn _remove(&mut self, key: &K) -> Option<V> {
-        let mut curr_node_index = self.root as u32;
+        let mut curr_node_index = self.root;
         if curr_node_index == SENTINEL {
             return None;
         }
@@ -563,10 +1340,17 @@ impl<
         }
     }
 
+    /// Removes the node at `node_index` from the tree. When the node has two children, its
+    /// in-order predecessor is relinked into its position rather than copied into its slot, so
+    /// the addresses of all other nodes are unaffected.
     fn _remove_tree_node(&mut self, node_index: u32) {
+        #[cfg(debug_assertions)]
+        let key = self.get_node(node_index).key;
         let mut is_black = self.is_black(node_index);
         let left = self.get_left(node_index);
         let right = self.get_right(node_index);
+        // The lowest node whose subtree shrinks as a result of the removal
+        let mut resized_node_index = self.get_parent(node_index);
         let (pivot_node_index, parent_and_dir) = if self.is_leaf(node_index) {
             if !self.is_root(node_index) {
                 let parent = self.get_parent(node_index);
@@ -596,32 +1380,40 @@ impl<
             // If max_left is not equal to root of the left subtree, then
             // replace the root of the left subtree with max_left and replace
             // max_left with max_left_child
+            resized_node_index = if max_left_parent != node_index {
+                max_left_parent
+            } else {
+                max_left
+            };
             if self.get_parent(max_left) != node_index {
                 self._transplant(max_left, max_left_child);
                 // We perform this operation in the conditional because we do not
                 // want to form a cycle
-                self._connect(max_left, self.get_left(node_index), Field::Left as u32);
+                self._connect(max_left, self.get_left(node_index), Field::Left);
                 if max_left_child == SENTINEL {
-                    parent_and_dir = Some((max_left_parent, Field::Right as u32));
+                    parent_and_dir = Some((max_left_parent, Field::Right));
                 }
             } else if max_left_child == SENTINEL {
                 // The only time this is called is when the left subtree is
                 // a single node
                 assert!(self.is_leaf(max_left));
-                parent_and_dir = Some((max_left, Field::Left as u32));
+                parent_and_dir = Some((max_left, Field::Left));
             }
 
             // Complete the transplant of max_left
             self._transplant(node_index, max_left);
-            self._connect(max_left, self.get_right(node_index), Field::Right as u32);
+            self._connect(max_left, self.get_right(node_index), Field::Right);
 
             self._color_node(max_left, self.get_color(node_index));
 
             (max_left_child, parent_and_dir)
         };
 
+        self._update_ancestor_sizes(resized_node_index);
+
         // Completely remove the current node index from the tree
         self._remove_allocator_node(node_index);
+        self.version += 1;
 
         if is_black {
             if self.is_root(pivot_node_index) {
@@ -630,37 +1422,39 @@ impl<
                 self._fix_remove(pivot_node_index, parent_and_dir);
             }
         }
+        #[cfg(debug_assertions)]
+        self._debug_check_invariants(&key);
     }
 
-    fn _fix_remove(&mut self, mut node_index: u32, parent_and_dir: Option<(u32, u32)>) {
+    fn _fix_remove(&mut self, mut node_index: u32, parent_and_dir: Option<(u32, Field)>) {
         let (mut parent, mut dir) = parent_and_dir.unwrap_or({
             let parent = self.get_parent(node_index);
             let dir = self._child_dir(parent, node_index);
             (parent, dir)
         });
         loop {
-            let mut sibling = self.get_child(parent, opposite(dir));
+            let mut sibling = self._child(parent, opposite(dir));
             if self.is_red(sibling) {
                 self._color_black(sibling);
                 self._color_red(parent);
                 self._rotate_dir(parent, dir);
-                sibling = self.get_dir(parent, opposite(dir));
+                sibling = self._child(parent, opposite(dir));
             }
             if self.is_black(self.get_left(sibling)) && self.is_black(self.get_right(sibling)) {
                 self._color_red(sibling);
                 node_index = parent;
             } else {
-                if self.is_black(self.get_dir(sibling, opposite(dir))) {
-                    self._color_black(self.get_dir(sibling, dir));
+                if self.is_black(self._child(sibling, opposite(dir))) {
+                    self._color_black(self._child(sibling, dir));
                     self._color_red(sibling);
                     self._rotate_dir(sibling, opposite(dir));
-                    sibling = self.get_dir(parent, opposite(dir));
+                    sibling = self._child(parent, opposite(dir));
                 }
                 self._color_node(sibling, self.get_color(parent));
                 self._color_black(parent);
-                self._color_black(self.get_dir(sibling, opposite(dir)));
+                self._color_black(self._child(sibling, opposite(dir)));
                 self._rotate_dir(parent, dir);
-                node_index = self.root as u32;
+                node_index = self.root;
             }
             if self.is_root(node_index) || self.is_red(node_index) {
                 break;
@@ -678,8 +1472,7 @@ impl<
         let parent = self.get_parent(target);
         if parent == SENTINEL {
             self.root = source;
-            self.allocator
-                .set_register(source, SENTINEL, Field::Parent as u32);
+            self.allocator.set_field(source, SENTINEL, Field::Parent);
             return;
         }
         let dir = self._child_dir(parent, target);
@@ -731,6 +1524,7 @@ impl<
             rev_ptr: self.root,
             rev_node: None,
             terminated: false,
+            remaining: self.len(),
         }
     }
 
@@ -777,31 +1571,212 @@ impl<
     }
 }
 
-pub struct RedBlackTreeIterator<
+/// Consumes the tree, yielding the entries by value in sorted order. Since the entries are `Copy`,
+/// they are snapshotted into a buffer sized to the number of entries rather than to `MAX_SIZE`.
+impl<
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > IntoIterator for RedBlackTree<K, V, MAX_SIZE>
+{
+    type Item = (K, V);
+    type IntoIter = alloc::vec::IntoIter<(K, V)>;
+    fn into_iter(self) -> Self::IntoIter {
+        self._iter()
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// A view into a single key of a `RedBlackTree`, which is either occupied or vacant.
+pub enum Entry<
     'a,
     K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
     V: Default + Copy + Clone + Pod + Zeroable,
     const MAX_SIZE: usize,
 > {
-    tree: &'a RedBlackTree<K, V, MAX_SIZE>,
-    fwd_stack: Vec<u32>,
-    fwd_ptr: u32,
-    fwd_node: Option<u32>,
-    rev_stack: Vec<u32>,
-    rev_ptr: u32,
-    rev_node: Option<u32>,
-    terminated: bool,
+    Occupied(OccupiedEntry<'a, K, V, MAX_SIZE>),
+    Vacant(VacantEntry<'a, K, V, MAX_SIZE>),
 }
 
-impl<
-        'a,
-        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
-        V: Default + Copy + Clone + Pod + Zeroable,
-        const MAX_SIZE: usize,
-    > Iterator for RedBlackTreeIterator<'a, K, V, MAX_SIZE>
-{
-    type Item = (&'a K, &'a V);
-
+pub struct OccupiedEntry<
+    'a,
+    K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+> {
+    tree: &'a mut RedBlackTree<K, V, MAX_SIZE>,
+    node_index: u32,
+}
+
+pub struct VacantEntry<
+    'a,
+    K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+> {
+    tree: &'a mut RedBlackTree<K, V, MAX_SIZE>,
+    key: K,
+}
+
+impl<
+        'a,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > Entry<'a, K, V, MAX_SIZE>
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value for the key, inserting `default` if the key is missing. Returns `None`
+    /// if the key is missing and the tree is full.
+    pub fn or_insert(self, default: V) -> Option<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value for the key, inserting the result of `f` if the key is missing.
+    /// Returns `None` if the key is missing and the tree is full.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    pub fn or_default(self) -> Option<&'a mut V> {
+        self.or_insert_with(V::default)
+    }
+
+    /// Applies `f` to the value if the key is present.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<
+        'a,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > OccupiedEntry<'a, K, V, MAX_SIZE>
+{
+    pub fn key(&self) -> &K {
+        &self.tree.get_node(self.node_index).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.tree.get_node(self.node_index).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.tree.get_node_mut(self.node_index).value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.tree.get_node_mut(self.node_index).value
+    }
+
+    /// Replaces the value of the entry, returning the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the tree, returning its value.
+    pub fn remove(self) -> V {
+        let value = *self.get();
+        self.tree._remove_tree_node(self.node_index);
+        value
+    }
+}
+
+impl<
+        'a,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > VacantEntry<'a, K, V, MAX_SIZE>
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts the key with `value`, returning a reference to the value. Returns `None` if the
+    /// tree is full.
+    pub fn insert(self, value: V) -> Option<&'a mut V> {
+        let node_index = self.tree._insert(self.key, value)?;
+        Some(&mut self.tree.get_node_mut(node_index).value)
+    }
+}
+
+/// Double-ended in-order iterator over a `RedBlackTree`. The iterator only holds a shared
+/// reference to the tree, so it can be sent to (or created in) another thread that borrows the
+/// tree, e.g. with `std::thread::scope`.
+pub struct RedBlackTreeIterator<
+    'a,
+    K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+    V: Default + Copy + Clone + Pod + Zeroable,
+    const MAX_SIZE: usize,
+> {
+    tree: &'a RedBlackTree<K, V, MAX_SIZE>,
+    fwd_stack: Vec<u32>,
+    fwd_ptr: u32,
+    fwd_node: Option<u32>,
+    rev_stack: Vec<u32>,
+    rev_ptr: u32,
+    rev_node: Option<u32>,
+    terminated: bool,
+    remaining: usize,
+}
+
+impl<
+        'a,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > RedBlackTreeIterator<'a, K, V, MAX_SIZE>
+{
+    /// Returns the number of entries that have not yet been yielded from either end
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Returns true if both ends of the iterator have met and no entries are left
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Rewinds the iterator to the start of the tree in both directions. The traversal stacks
+    /// are cleared rather than dropped, so their allocations are reused by the next pass.
+    pub fn reset(&mut self) {
+        self.fwd_stack.clear();
+        self.fwd_ptr = self.tree.root;
+        self.fwd_node = None;
+        self.rev_stack.clear();
+        self.rev_ptr = self.tree.root;
+        self.rev_node = None;
+        self.terminated = false;
+        self.remaining = self.tree.len();
+    }
+}
+
+impl<
+        'a,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > Iterator for RedBlackTreeIterator<'a, K, V, MAX_SIZE>
+{
+    type Item = (&'a K, &'a V);
+
     fn next(&mut self) -> Option<Self::Item> {
         while !self.terminated && (!self.fwd_stack.is_empty() || self.fwd_ptr != SENTINEL) {
             if self.fwd_ptr != SENTINEL {
@@ -809,11 +1784,12 @@ impl<
                 self.fwd_ptr = self.tree.get_left(self.fwd_ptr);
             } else {
                 let current_node = self.fwd_stack.pop();
-                if current_node == self.rev_node {
+                if current_node == self.rev_node || self.remaining == 0 {
                     self.terminated = true;
                     return None;
                 }
                 self.fwd_node = current_node;
+                self.remaining -= 1;
                 let node = self.tree.get_node(current_node.unwrap());
                 self.fwd_ptr = self.tree.get_right(current_node.unwrap());
                 return Some((&node.key, &node.value));
@@ -821,6 +1797,10 @@ impl<
         }
         None
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
 impl<
@@ -837,11 +1817,14 @@ impl<
                 self.rev_ptr = self.tree.get_right(self.rev_ptr);
             } else {
                 let current_node = self.rev_stack.pop();
-                if current_node == self.fwd_node {
+                // `remaining` also bounds iterators seeded by `iter_from`, whose forward side
+                // has not visited the keys below the seek key
+                if current_node == self.fwd_node || self.remaining == 0 {
                     self.terminated = true;
                     return None;
                 }
                 self.rev_node = current_node;
+                self.remaining -= 1;
                 let node = self.tree.get_node(current_node.unwrap());
                 self.rev_ptr = self.tree.get_left(current_node.unwrap());
                 return Some((&node.key, &node.value));
@@ -851,6 +1834,15 @@ impl<
     }
 }
 
+impl<
+        'a,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > ExactSizeIterator for RedBlackTreeIterator<'a, K, V, MAX_SIZE>
+{
+}
+
 pub struct RedBlackTreeIteratorMut<
     'a,
     K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
@@ -945,6 +1937,18 @@ impl<
     }
 }
 
+impl<
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Debug + Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > Debug for RedBlackTree<K, V, MAX_SIZE>
+{
+    /// Formats the entries of the tree as a map in key order
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self._iter()).finish()
+    }
+}
+
 impl<
         K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
         V: Default + Copy + Clone + Pod + Zeroable,
@@ -976,7 +1980,7 @@ fn test_insert_with_red_parent_and_uncle() {
     type Rbt = RedBlackTree<u64, u64, 1024>;
     let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
     let tree = Rbt::new_from_slice(buf.as_mut_slice());
-    let addrs = vec![
+    let addrs = [
         tree.insert(61, 0).unwrap(),
         tree.insert(52, 0).unwrap(),
         tree.insert(85, 0).unwrap(),
@@ -1025,7 +2029,7 @@ fn test_right_insert_with_red_right_child_parent_and_black_uncle() {
     type Rbt = RedBlackTree<u64, u64, 1024>;
     let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
     let tree = Rbt::new_from_slice(buf.as_mut_slice());
-    let addrs = vec![
+    let addrs = [
         tree.insert(61, 0).unwrap(),
         tree.insert(52, 0).unwrap(),
         tree.insert(85, 0).unwrap(),
@@ -1078,7 +2082,7 @@ fn test_left_insert_with_red_right_child_parent_and_black_uncle() {
     type Rbt = RedBlackTree<u64, u64, 1024>;
     let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
     let tree = Rbt::new_from_slice(buf.as_mut_slice());
-    let addrs = vec![
+    let addrs = [
         tree.insert(61, 0).unwrap(),
         tree.insert(52, 0).unwrap(),
         tree.insert(85, 0).unwrap(),
@@ -1131,7 +2135,7 @@ fn test_left_insert_with_red_left_child_parent_and_black_uncle() {
     type Rbt = RedBlackTree<u64, u64, 1024>;
     let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
     let tree = Rbt::new_from_slice(buf.as_mut_slice());
-    let addrs = vec![
+    let addrs = [
         tree.insert(61, 0).unwrap(),
         tree.insert(85, 0).unwrap(),
         tree.insert(52, 0).unwrap(),
@@ -1184,7 +2188,7 @@ fn test_right_insert_with_red_left_child_parent_and_black_uncle() {
     type Rbt = RedBlackTree<u64, u64, 1024>;
     let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
     let tree = Rbt::new_from_slice(buf.as_mut_slice());
-    let addrs = vec![
+    let addrs = [
         tree.insert(61, 0).unwrap(),
         tree.insert(85, 0).unwrap(),
         tree.insert(52, 0).unwrap(),
@@ -1383,3 +2387,1151 @@ fn remove_root() {
     assert_eq!(root.key, 1);
     assert_eq!(root.value, 5);
 }
+
+#[test]
+fn test_version() {
+    type Rbt = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.version(), 0);
+
+    tree.insert(1, 0).unwrap();
+    tree.insert(2, 0).unwrap();
+    assert_eq!(tree.version(), 2);
+
+    // Reads and in-place value updates do not bump the version
+    assert_eq!(tree.get(&1), Some(&0));
+    assert!(tree.contains(&2));
+    tree.iter().for_each(drop);
+    tree.insert(1, 5).unwrap();
+    *tree.get_mut(&2).unwrap() = 7;
+    assert_eq!(tree.version(), 2);
+
+    // Removing a missing key is not a mutation
+    assert!(tree.remove(&3).is_none());
+    assert_eq!(tree.version(), 2);
+
+    tree.remove(&1).unwrap();
+    assert_eq!(tree.version(), 3);
+    tree.remove_root().unwrap();
+    assert_eq!(tree.version(), 4);
+}
+
+#[test]
+fn test_intern() {
+    type Rbt = RedBlackTree<u64, u64, 4>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let a = tree.intern(10, 1).unwrap();
+    assert_eq!(tree.intern(10, 2), Some(a));
+    assert_eq!(tree.get(&10), Some(&1));
+
+    let b = tree.intern(20, 3).unwrap();
+    assert_ne!(a, b);
+    for _ in 0..3 {
+        assert_eq!(tree.intern(20, 4), Some(b));
+    }
+    assert_eq!(tree.get(&20), Some(&3));
+    assert_eq!(tree.len(), 2);
+
+    tree.intern(30, 0).unwrap();
+    tree.intern(40, 0).unwrap();
+    // Existing keys still resolve when the tree is full, new ones are rejected
+    assert_eq!(tree.intern(10, 5), Some(a));
+    assert!(tree.intern(50, 0).is_none());
+    assert!(tree.is_valid_red_black_tree());
+}
+
+#[test]
+fn test_try_insert_batch() {
+    type Rbt = RedBlackTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert_eq!(
+        tree.try_insert_batch(&[(1, 1), (2, 2), (3, 3), (2, 4)]),
+        Ok(3)
+    );
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree.get(&2), Some(&4));
+
+    // Existing keys do not count against the remaining capacity
+    let batch = [(1, 10), (4, 4), (5, 5), (6, 6), (7, 7), (8, 8)];
+    assert_eq!(tree.try_insert_batch(&batch), Ok(5));
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.get(&1), Some(&10));
+
+    // An over-capacity batch leaves the tree untouched
+    let snapshot = tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+    let version = tree.version();
+    assert_eq!(
+        tree.try_insert_batch(&[(1, 100), (9, 9)]),
+        Err(SokobanError::Full)
+    );
+    assert_eq!(
+        tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+        snapshot
+    );
+    assert_eq!(tree.version(), version);
+    assert!(tree.is_valid_red_black_tree());
+}
+
+#[test]
+fn test_map_values() {
+    #[repr(C)]
+    #[derive(Default, Copy, Clone, Debug, PartialEq)]
+    struct Wide {
+        value: u64,
+        doubled: u128,
+    }
+    unsafe impl Zeroable for Wide {}
+    unsafe impl Pod for Wide {}
+
+    type Rbt = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for k in [17, 3, 42, 8, 99, 1] {
+        tree.insert(k, k * 10);
+    }
+    let mut new_buf = vec![0u8; std::mem::size_of::<RedBlackTree<u64, Wide, 64>>()];
+    let wide = tree.map_values(new_buf.as_mut_slice(), |v| Wide {
+        value: *v,
+        doubled: *v as u128 * 2,
+    });
+    assert!(wide.is_valid_red_black_tree());
+    assert_eq!(wide.len(), tree.len());
+    for ((k1, v1), (k2, v2)) in tree.iter().zip(wide.iter()) {
+        assert_eq!(k1, k2);
+        assert_eq!(v2.value, *v1);
+        assert_eq!(v2.doubled, *v1 as u128 * 2);
+    }
+}
+
+#[test]
+fn test_contains_all_and_any() {
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let mut rng = rand::thread_rng();
+    for _ in 0..200 {
+        let k = rng.gen_range(0, 400);
+        tree.insert(k, k);
+    }
+    assert!(tree.contains_all(&[]));
+    assert!(!tree.contains_any(&[]));
+    for _ in 0..500 {
+        let n = rng.gen_range(1, 6);
+        let mut keys = (0..n).map(|_| rng.gen_range(0, 400)).collect::<Vec<u64>>();
+        let all = keys.iter().all(|k| tree.contains(k));
+        let any = keys.iter().any(|k| tree.contains(k));
+        assert_eq!(tree.contains_all(&keys), all);
+        assert_eq!(tree.contains_any(&keys), any);
+        keys.sort();
+        assert_eq!(tree.contains_all(&keys), all);
+        keys.shuffle(&mut rng);
+        assert_eq!(tree.contains_all(&keys), all);
+    }
+    let mut present = tree.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    present.push(present[0]);
+    assert!(tree.contains_all(&present));
+    present.sort();
+    assert!(tree.contains_all(&present));
+}
+
+#[test]
+fn test_rank_range() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 128>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.rank_range(..), (0, 0));
+    let mut rng = rand::thread_rng();
+    for _ in 0..100 {
+        let k = rng.gen_range(0, 1000);
+        tree.insert(k, k);
+    }
+    let keys = tree.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    assert_eq!(tree.rank_range(..), (0, tree.len()));
+    for _ in 0..500 {
+        let a = rng.gen_range(0, 1000);
+        let b = rng.gen_range(a, 1000);
+        for (range, (start, end)) in [
+            (
+                (Bound::Included(a), Bound::Excluded(b)),
+                tree.rank_range(a..b),
+            ),
+            (
+                (Bound::Included(a), Bound::Included(b)),
+                tree.rank_range(a..=b),
+            ),
+            (
+                (Bound::Excluded(a), Bound::Unbounded),
+                tree.rank_range((Bound::Excluded(a), Bound::Unbounded)),
+            ),
+            (
+                (Bound::Unbounded, Bound::Included(b)),
+                tree.rank_range(..=b),
+            ),
+        ] {
+            let expected = keys.iter().filter(|k| range.contains(*k)).count();
+            assert_eq!(end - start, expected);
+            if expected > 0 {
+                assert!(range.contains(&keys[start]));
+                assert!(range.contains(&keys[end - 1]));
+            }
+        }
+    }
+    let (start, end) = tree.rank_range((Bound::Included(10), Bound::Excluded(5)));
+    assert_eq!(start, end);
+}
+
+#[test]
+fn test_iter_is_send() {
+    fn assert_send<T: Send>(_: &T) {}
+    type Rbt = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for k in 0..64 {
+        tree.insert(k, k * 2);
+    }
+    let tree = &*tree;
+    let iter = tree.iter();
+    assert_send(&iter);
+    let (fwd, rev) = std::thread::scope(|s| {
+        let fwd = s.spawn(move || iter.map(|(k, v)| k + v).sum::<u64>());
+        let rev = s.spawn(|| tree.iter().rev().map(|(k, _)| *k).collect::<Vec<_>>());
+        (fwd.join().unwrap(), rev.join().unwrap())
+    });
+    assert_eq!(fwd, (0..64).map(|k| k * 3).sum::<u64>());
+    assert_eq!(rev, (0..64).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_entry() {
+    type Rbt = RedBlackTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for k in 0..7 {
+        *tree.entry(k).or_insert(0).unwrap() += k;
+        assert!(tree.is_valid_red_black_tree());
+    }
+    for k in 0..7 {
+        *tree.entry(k).or_insert(100).unwrap() += 1;
+        assert!(tree.is_valid_red_black_tree());
+    }
+    assert_eq!(tree.len(), 7);
+    for k in 0..7 {
+        assert_eq!(tree.get(&k), Some(&(k + 1)));
+    }
+
+    tree.entry(3).and_modify(|v| *v *= 10).or_insert(0);
+    assert_eq!(tree.get(&3), Some(&40));
+    tree.entry(7).and_modify(|v| *v *= 10).or_insert_with(|| 7);
+    assert_eq!(tree.get(&7), Some(&7));
+
+    // The tree is full, so vacant entries can't be filled
+    assert!(tree.entry(8).or_default().is_none());
+    assert_eq!(tree.len(), 8);
+    assert_eq!(tree.entry(7).or_default(), Some(&mut 7));
+
+    match tree.entry(3) {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.insert(1), 40);
+            assert_eq!(entry.remove(), 1);
+        }
+        Entry::Vacant(_) => panic!("expected an occupied entry"),
+    }
+    assert!(matches!(tree.entry(3), Entry::Vacant(_)));
+    assert_eq!(tree.len(), 7);
+    assert!(tree.is_valid_red_black_tree());
+}
+
+#[test]
+fn test_partition() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 128>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for _ in 0..128 {
+        let k = rng.gen::<u64>();
+        tree.insert(k, rng.gen());
+    }
+    let mut yes_buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let mut no_buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let yes = Rbt::new_from_slice(yes_buf.as_mut_slice());
+    let no = Rbt::new_from_slice(no_buf.as_mut_slice());
+    assert!(tree.partition(yes, no, |_, v| v % 2 == 0).is_ok());
+    assert!(yes.is_valid_red_black_tree() && no.is_valid_red_black_tree());
+    assert_eq!(yes.len() + no.len(), tree.len());
+    assert!(yes.iter().all(|(_, v)| v % 2 == 0));
+    assert!(no.iter().all(|(_, v)| v % 2 == 1));
+    for (k, v) in tree.iter() {
+        assert_eq!(yes.get(k).or_else(|| no.get(k)), Some(v));
+    }
+
+    // Partitioning into trees that are too small stops with an error
+    type Small = RedBlackTree<u64, u64, 4>;
+    let mut small_buf = vec![0u8; std::mem::size_of::<Small>()];
+    let small = Small::new_from_slice(small_buf.as_mut_slice());
+    for k in 0..4 {
+        small.insert(k, k);
+    }
+    let mut a_buf = vec![0u8; std::mem::size_of::<Small>()];
+    let mut b_buf = vec![0u8; std::mem::size_of::<Small>()];
+    let a = Small::new_from_slice(a_buf.as_mut_slice());
+    let b = Small::new_from_slice(b_buf.as_mut_slice());
+    a.insert(100, 100);
+    a.insert(101, 101);
+    a.insert(102, 102);
+    assert_eq!(small.partition(a, b, |_, _| true), Err(SokobanError::Full));
+    assert_eq!(a.len(), 4);
+    assert!(b.is_empty());
+}
+
+#[test]
+fn test_select_and_rank() {
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 512>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let mut rng = rand::thread_rng();
+    let mut keys: Vec<u64> = vec![];
+    assert!(tree.select(0).is_none());
+    assert_eq!(tree.rank(&0), 0);
+    for round in 0..2000 {
+        if keys.len() < tree.capacity() && (keys.is_empty() || rng.gen_range(0, 3) > 0) {
+            let k = rng.gen_range(0, 2048);
+            tree.insert(k, k + 1);
+            if let Err(i) = keys.binary_search(&k) {
+                keys.insert(i, k);
+            }
+        } else {
+            let k = *keys.choose(&mut rng).unwrap();
+            assert_eq!(tree.remove(&k), Some(k + 1));
+            keys.retain(|x| *x != k);
+        }
+        if round % 50 == 0 {
+            assert!(tree.is_valid_red_black_tree());
+        }
+        assert_eq!(tree.subtree_size(tree.root), keys.len());
+        for _ in 0..4 {
+            let i = rng.gen_range(0, keys.len() + 1);
+            assert_eq!(tree.select(i).map(|(k, _)| *k), keys.get(i).copied());
+            let probe = rng.gen_range(0, 2049);
+            assert_eq!(tree.rank(&probe), keys.partition_point(|k| *k < probe));
+        }
+    }
+    for (i, k) in keys.iter().enumerate() {
+        assert_eq!(tree.rank(k), i);
+        assert_eq!(tree.select(i), Some((k, &(k + 1))));
+    }
+}
+
+#[test]
+fn test_split_off() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 128>;
+    let mut rng = rand::thread_rng();
+    for split in [0, 500, 1000, 2000] {
+        let mut tree = Rbt::new();
+        for _ in 0..128 {
+            let k = rng.gen_range(0, 1000);
+            tree.insert(k, k * 2);
+        }
+        let original = tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        let other = tree.split_off(&split);
+        assert!(tree.is_valid_red_black_tree());
+        assert!(other.is_valid_red_black_tree());
+        assert_eq!(tree.len() + other.len(), original.len());
+        assert!(tree.iter().all(|(k, _)| *k < split));
+        assert!(other.iter().all(|(k, _)| *k >= split));
+        let rejoined = tree
+            .iter()
+            .chain(other.iter())
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>();
+        assert_eq!(rejoined, original);
+    }
+}
+
+#[test]
+fn test_retain() {
+    use rand::Rng;
+    use std::collections::BTreeMap;
+    type Rbt = RedBlackTree<u64, u64, 2048>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let mut map = BTreeMap::new();
+    let mut rng = rand::thread_rng();
+    for _ in 0..2048 {
+        let (k, v) = (rng.gen::<u64>(), rng.gen::<u64>());
+        tree.insert(k, v);
+        map.insert(k, v);
+    }
+    let pred = |_: &u64, v: &mut u64| {
+        *v += 1;
+        !v.is_multiple_of(3)
+    };
+    tree.retain(pred);
+    map.retain(pred);
+    assert!(tree.is_valid_red_black_tree());
+    assert_eq!(tree.len(), map.len());
+    assert!(tree.iter().eq(map.iter()));
+    tree.retain(|_, _| false);
+    assert!(tree.is_empty());
+    assert!(tree.is_valid_red_black_tree());
+}
+
+#[test]
+fn test_copy_range_into_deque() {
+    type Rbt = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for k in (0..64).rev() {
+        tree.insert(k * 10, k);
+    }
+    type Q = Deque<RBNode<u64, u64>, 8>;
+    let mut deque_buf = vec![0u8; std::mem::size_of::<Q>()];
+    let deque = Q::new_from_slice(deque_buf.as_mut_slice());
+    assert_eq!(tree.copy_range_into_deque(95..=130, deque), 4);
+    let copied = deque
+        .iter()
+        .map(|(_, n)| (n.key, n.value))
+        .collect::<Vec<_>>();
+    assert_eq!(copied, vec![(100, 10), (110, 11), (120, 12), (130, 13)]);
+
+    // Only the remaining capacity of the deque is filled
+    assert_eq!(tree.copy_range_into_deque(200.., deque), 4);
+    assert_eq!(deque.len(), 8);
+    assert_eq!(deque.back().map(|n| n.key), Some(230));
+    assert_eq!(tree.copy_range_into_deque(..10, deque), 0);
+}
+
+#[test]
+fn test_pop_first_and_last() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert!(tree.pop_first().is_none() && tree.pop_last().is_none());
+    let mut rng = rand::thread_rng();
+    for _ in 0..256 {
+        let k = rng.gen::<u64>();
+        tree.insert(k, !k);
+    }
+    let mut expected = tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+    let mut popped = vec![];
+    while tree.len() > 1 {
+        popped.push(tree.pop_first().unwrap());
+        let last = tree.pop_last().unwrap();
+        assert_eq!(Some(&last), expected.last());
+        expected.pop();
+        assert!(tree.is_valid_red_black_tree());
+    }
+    if tree.len() == 1 {
+        popped.push(tree.pop_first().unwrap());
+    }
+    assert_eq!(tree.root, SENTINEL);
+    assert!(tree.is_empty());
+    assert_eq!(popped, expected);
+}
+
+#[test]
+fn test_successor_and_predecessor() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 512>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.successor(SENTINEL), SENTINEL);
+    let mut rng = rand::thread_rng();
+    for _ in 0..512 {
+        let k = rng.gen_range(0, 10000);
+        tree.insert(k, k);
+    }
+    let keys = tree.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    let mut forward = vec![];
+    let mut node = tree.get_addr(&keys[0]);
+    while node != SENTINEL {
+        forward.push(tree.get_node(node).key);
+        node = tree.successor(node);
+    }
+    assert_eq!(forward, keys);
+    let mut backward = vec![];
+    let mut node = tree.get_addr(keys.last().unwrap());
+    while node != SENTINEL {
+        backward.push(tree.get_node(node).key);
+        node = tree.predecessor(node);
+    }
+    backward.reverse();
+    assert_eq!(backward, keys);
+}
+
+#[test]
+fn test_iter_reset() {
+    type Rbt = RedBlackTree<u64, u64, 128>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for k in (0..128).rev() {
+        tree.insert(k, k);
+    }
+    let expected = (0..128).collect::<Vec<u64>>();
+    let mut iter = tree.iter();
+    assert_eq!(iter.by_ref().map(|(k, _)| *k).collect::<Vec<_>>(), expected);
+    assert!(iter.next().is_none());
+    let capacity = iter.fwd_stack.capacity();
+    iter.reset();
+    assert_eq!(iter.fwd_stack.capacity(), capacity);
+    assert_eq!(iter.by_ref().map(|(k, _)| *k).collect::<Vec<_>>(), expected);
+
+    // Reset a partially consumed, double-ended traversal
+    iter.reset();
+    assert_eq!(iter.next().map(|(k, _)| *k), Some(0));
+    assert_eq!(iter.next_back().map(|(k, _)| *k), Some(127));
+    iter.reset();
+    assert_eq!(
+        iter.rev().map(|(k, _)| *k).collect::<Vec<_>>(),
+        expected.iter().rev().copied().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_iter_remaining() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert!(tree.iter().is_empty());
+    let mut rng = rand::thread_rng();
+    for _ in 0..200 {
+        let k = rng.gen::<u64>();
+        tree.insert(k, k);
+    }
+
+    // Interleave both ends and check the count before every step
+    let mut iter = tree.iter();
+    assert_eq!(iter.remaining(), tree.len());
+    loop {
+        let remaining = iter.remaining();
+        assert_eq!(iter.len(), remaining);
+        assert_eq!(iter.is_empty(), remaining == 0);
+        let item = if rng.gen::<bool>() {
+            iter.next()
+        } else {
+            iter.next_back()
+        };
+        if item.is_none() {
+            assert_eq!(remaining, 0);
+            break;
+        }
+        assert_eq!(iter.remaining(), remaining - 1);
+    }
+    assert!(iter.next().is_none() && iter.next_back().is_none());
+
+    iter.reset();
+    assert_eq!(iter.remaining(), tree.len());
+    let remaining = iter.remaining();
+    assert_eq!(iter.by_ref().count(), remaining);
+    assert!(iter.is_empty());
+}
+
+#[test]
+fn test_into_iter_by_value() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 256>;
+    let mut tree = Box::<Rbt>::default();
+    let mut rng = rand::thread_rng();
+    let mut expected = vec![];
+    for _ in 0..200 {
+        let k = rng.gen::<u64>();
+        tree.insert(k, k / 3);
+        expected.push((k, k / 3));
+    }
+    expected.sort_unstable();
+    expected.dedup();
+
+    let by_ref = (&*tree)
+        .into_iter()
+        .map(|(k, v)| (*k, *v))
+        .collect::<Vec<_>>();
+    assert_eq!(by_ref, expected);
+    let mut consumed = vec![];
+    for (k, v) in *tree {
+        consumed.push((k, v));
+    }
+    assert_eq!(consumed, expected);
+    assert_eq!(
+        (*tree).into_iter().rev().collect::<Vec<_>>(),
+        expected.iter().rev().copied().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_keys_and_values() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let mut rng = rand::thread_rng();
+    for _ in 0..256 {
+        let k = rng.gen::<u64>();
+        tree.insert(k, k / 2);
+    }
+    let mut sorted = tree.keys().copied().collect::<Vec<_>>();
+    assert!(sorted.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(sorted.len(), tree.len());
+    let reversed = tree.keys().rev().copied().collect::<Vec<_>>();
+    sorted.reverse();
+    assert_eq!(reversed, sorted);
+    sorted.reverse();
+
+    tree.values_mut().rev().for_each(|v| *v += 1);
+    assert!(tree
+        .values()
+        .zip(sorted.iter())
+        .all(|(v, k)| *v == k / 2 + 1));
+}
+
+#[test]
+fn test_addr_range() {
+    use rand::Rng;
+    type Rbt = RedBlackTree<u64, u64, 256>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.addr_range(..).count(), 0);
+    let mut rng = rand::thread_rng();
+    for _ in 0..256 {
+        let k = rng.gen_range(0, 1000);
+        tree.insert(k, k);
+    }
+    let keys = tree.keys().copied().collect::<Vec<_>>();
+    assert_eq!(tree.addr_range(..).count(), tree.len());
+    for _ in 0..200 {
+        let a = rng.gen_range(0, 1000);
+        let b = rng.gen_range(a, 1000);
+        let addrs = tree.addr_range(a..b).collect::<Vec<_>>();
+        let expected = keys
+            .iter()
+            .filter(|k| (a..b).contains(*k))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addrs
+                .iter()
+                .map(|i| &tree.get_node(*i).key)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        let addrs = tree
+            .addr_range((Bound::Excluded(a), Bound::Included(b)))
+            .collect::<Vec<_>>();
+        assert!(addrs.iter().all(|i| {
+            let k = tree.get_node(*i).key;
+            k > a && k <= b && tree.get_addr(&k) == *i
+        }));
+        assert_eq!(
+            addrs.len(),
+            keys.iter().filter(|k| **k > a && **k <= b).count()
+        );
+    }
+}
+
+#[test]
+fn test_debug() {
+    type Rbt = RedBlackTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    assert_eq!(format!("{:?}", tree), "{}");
+    for k in [3, 1, 2] {
+        tree.insert(k, k * 10);
+    }
+    assert_eq!(format!("{:?}", tree), "{1: 10, 2: 20, 3: 30}");
+}
+
+#[test]
+fn test_from_sorted_slice() {
+    type Rbt = RedBlackTree<u64, u64, 1024>;
+    for n in [0, 1, 2, 3, 4, 7, 100, 1023, 1024] {
+        let items = (0..n).map(|k| (k * 3, k)).collect::<Vec<_>>();
+        let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+        let tree = Rbt::from_sorted_slice(buf.as_mut_slice(), &items);
+        assert!(tree.is_valid_red_black_tree());
+        assert!(tree.verify_parent_pointers());
+        assert_eq!(tree.len(), items.len());
+        assert_eq!(
+            tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            items
+        );
+        // The tree behaves normally after bulk loading
+        tree.remove(&0);
+        tree.insert(1, 1);
+        assert!(tree.is_valid_red_black_tree());
+        assert_eq!(tree.select(0), Some((&1, &1)));
+    }
+}
+
+#[test]
+#[should_panic(expected = "Items must be sorted by key with no duplicates")]
+fn test_from_sorted_slice_rejects_duplicates() {
+    type Rbt = RedBlackTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    Rbt::from_sorted_slice(buf.as_mut_slice(), &[(1, 1), (2, 2), (2, 3)]);
+}
+
+#[test]
+#[should_panic(expected = "Items must be sorted by key with no duplicates")]
+fn test_from_sorted_slice_rejects_unsorted() {
+    type Rbt = RedBlackTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    Rbt::from_sorted_slice(buf.as_mut_slice(), &[(1, 1), (3, 3), (2, 2)]);
+}
+
+#[test]
+fn test_drain_filter_full_tree() {
+    use rand::Rng;
+    use std::collections::BTreeMap;
+    type Rbt = RedBlackTree<u64, u64, 4096>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let mut map = BTreeMap::new();
+    let mut rng = rand::thread_rng();
+    while tree.len() < tree.capacity() {
+        let (k, v) = (rng.gen::<u64>(), rng.gen::<u64>());
+        tree.insert(k, v);
+        map.insert(k, v);
+    }
+    let addrs = map
+        .keys()
+        .map(|k| (*k, tree.get_addr(k)))
+        .collect::<BTreeMap<_, _>>();
+    // Drain roughly 80% of the entries
+    let drained = tree.drain_filter(|_, v| !v.is_multiple_of(5));
+    let expected = map
+        .iter()
+        .filter(|(_, v)| !v.is_multiple_of(5))
+        .map(|(k, v)| (*k, *v))
+        .collect::<Vec<_>>();
+    map.retain(|_, v| v.is_multiple_of(5));
+    assert_eq!(drained, expected);
+    assert!(tree.is_valid_red_black_tree());
+    assert!(tree.verify_parent_pointers());
+    assert_eq!(tree.len(), map.len());
+    assert!(tree.iter().eq(map.iter()));
+    // Surviving entries have not moved
+    assert!(map.keys().all(|k| tree.get_addr(k) == addrs[k]));
+}
+
+#[test]
+fn test_get_or_insert_with() {
+    use rand::Rng;
+    use std::collections::BTreeMap;
+    type Rbt = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    let mut counts = BTreeMap::new();
+    let mut rng = rand::thread_rng();
+    for _ in 0..10000 {
+        let k = rng.gen_range(0, 64);
+        *tree.get_or_insert_with(k, || 0).unwrap() += 1;
+        *counts.entry(k).or_insert(0) += 1;
+    }
+    assert!(tree.iter().eq(counts.iter()));
+    assert!(tree.is_valid_red_black_tree());
+
+    // Existing keys never call `f`, even when the tree is full
+    for k in 0..64 {
+        tree.get_or_insert_with(k, || 0);
+    }
+    assert_eq!(tree.len(), tree.capacity());
+    assert!(tree.get_or_insert_with(64, || unreachable!()).is_none());
+    assert!(tree.get_or_insert_with(0, || unreachable!()).is_some());
+}
+
+#[test]
+fn test_keys_range() {
+    type Rbt = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Rbt>()];
+    let tree = Rbt::new_from_slice(buf.as_mut_slice());
+    for k in 0..64 {
+        tree.insert(k * 2, k);
+    }
+    assert_eq!(
+        tree.keys_range(9..=16).copied().collect::<Vec<_>>(),
+        vec![10, 12, 14, 16]
+    );
+    assert_eq!(
+        tree.keys_range(..4).copied().collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+    assert_eq!(
+        tree.keys_range(122..).copied().collect::<Vec<_>>(),
+        vec![122, 124, 126]
+    );
+    assert_eq!(tree.keys_range(200..).count(), 0);
+    assert!(tree.keys_range(..).eq(tree.keys()));
+}
+
+#[test]
+fn test_union_and_intersection() {
+    use rand::Rng;
+    use std::collections::BTreeMap;
+    type Tree = RedBlackTree<u64, u64, 64>;
+    let mut rng = rand::thread_rng();
+    for _ in 0..64 {
+        let mut a = Tree::new();
+        let mut b = Tree::new();
+        let mut std_a = BTreeMap::new();
+        let mut std_b = BTreeMap::new();
+        for _ in 0..rng.gen_range(0, 48) {
+            let (k, v) = (rng.gen_range(0, 96), rng.gen_range(0, 1000));
+            a.insert(k, v);
+            std_a.insert(k, v);
+        }
+        for _ in 0..rng.gen_range(0, 48) {
+            let (k, v) = (rng.gen_range(0, 96), rng.gen_range(0, 1000));
+            b.insert(k, v);
+            std_b.insert(k, v);
+        }
+        let mut std_union = std_b.clone();
+        std_union.extend(std_a.iter());
+        let std_intersection = std_a
+            .iter()
+            .filter(|(k, _)| std_b.contains_key(k))
+            .map(|(k, v)| (*k, *v))
+            .collect::<Vec<_>>();
+
+        // `out` starts with unrelated contents that must be replaced
+        let mut out = Tree::new();
+        out.insert(1000, 1000);
+        match a.union(&b, &mut out) {
+            Ok(len) => {
+                assert_eq!(len, std_union.len());
+                assert!(out.is_valid_red_black_tree());
+                assert_eq!(
+                    out.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+                    std_union.into_iter().collect::<Vec<_>>()
+                );
+            }
+            Err(e) => {
+                assert_eq!(e, SokobanError::Full);
+                assert!(std_union.len() > 64);
+                assert_eq!(out.len(), 1);
+                assert_eq!(out.get(&1000), Some(&1000));
+            }
+        }
+
+        let mut out = Tree::new();
+        assert_eq!(a.intersection(&b, &mut out), Ok(std_intersection.len()));
+        assert!(out.is_valid_red_black_tree());
+        assert_eq!(
+            out.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            std_intersection
+        );
+        // the result is a regular tree that can be modified
+        out.insert(2000, 0);
+        out.remove(&2000);
+        assert!(out.is_valid_red_black_tree());
+    }
+}
+
+#[test]
+fn test_try_insert_and_remaining_capacity() {
+    type Tree = RedBlackTree<u64, u64, 16>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for k in 0..16 {
+        assert_eq!(tree.remaining_capacity(), 16 - k as usize);
+        assert!(!tree.is_full());
+        assert!(tree.try_insert(k, k).is_ok());
+    }
+    // every slot is usable, so the tree is full at MAX_SIZE entries
+    assert!(tree.is_full());
+    assert_eq!(tree.remaining_capacity(), 0);
+    assert_eq!(tree.try_insert(16, 16), Err(SokobanError::Full));
+    assert_eq!(tree.insert(16, 16), None);
+    // overwrites do not need a free slot
+    let addr = tree.get_addr(&3);
+    assert_eq!(tree.try_insert(3, 30), Ok(addr));
+    assert_eq!(tree.get(&3), Some(&30));
+    assert!(tree.is_valid_red_black_tree());
+
+    tree.remove(&7);
+    assert!(!tree.is_full());
+    assert_eq!(tree.remaining_capacity(), 1);
+    assert!(tree.try_insert(16, 16).is_ok());
+    assert_eq!(tree.try_insert(17, 17), Err(SokobanError::Full));
+}
+
+#[test]
+fn test_copy_from() {
+    use rand::Rng;
+    type Tree = RedBlackTree<u64, u64, 256>;
+    let mut rng = rand::thread_rng();
+    let mut src_buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let mut dst_buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let src = Tree::new_from_slice(src_buf.as_mut_slice());
+    let dst = Tree::new_from_slice(dst_buf.as_mut_slice());
+    for _ in 0..200 {
+        dst.insert(rng.gen(), 0);
+    }
+    for _ in 0..100 {
+        src.insert(rng.gen_range(0, 1000), rng.gen());
+    }
+    for k in 0..500 {
+        src.remove(&k);
+    }
+    dst.copy_from(src);
+    assert!(dst.is_valid_red_black_tree());
+    assert_eq!(
+        dst.iter().collect::<Vec<_>>(),
+        src.iter().collect::<Vec<_>>()
+    );
+    // the copy is independent of the original and can be filled up
+    while !dst.is_full() {
+        dst.insert(rng.gen(), 1);
+    }
+    assert!(dst.is_valid_red_black_tree());
+    assert!(src.is_valid_red_black_tree());
+    assert!(src.len() < dst.len());
+}
+
+#[test]
+fn test_iter_from() {
+    use rand::Rng;
+    type Tree = RedBlackTree<u64, u64, 256>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    assert_eq!(tree.iter_from(&0).count(), 0);
+    for _ in 0..200 {
+        tree.insert(rng.gen_range(0, 1000), rng.gen());
+    }
+    for _ in 0..100 {
+        let k = rng.gen_range(0, 1100);
+        let expected = tree.iter().filter(|(x, _)| **x >= k).collect::<Vec<_>>();
+        let iter = tree.iter_from(&k);
+        assert_eq!(iter.len(), expected.len());
+        assert_eq!(iter.collect::<Vec<_>>(), expected);
+        let expected_rev = expected.iter().rev().copied().collect::<Vec<_>>();
+        assert_eq!(tree.iter_from(&k).rev().collect::<Vec<_>>(), expected_rev);
+    }
+    let max = *tree.iter().next_back().unwrap().0;
+    assert_eq!(tree.iter_from(&(max + 1)).count(), 0);
+    assert_eq!(tree.iter_from(&max).count(), 1);
+    assert_eq!(tree.iter_from(&0).count(), tree.len());
+}
+
+#[test]
+fn test_get_key_value() {
+    /// Key that is ordered by `id` only, so `tag` is carried along with the stored key
+    #[repr(C)]
+    #[derive(Debug, Default, Copy, Clone, Eq)]
+    struct TaggedKey {
+        id: u64,
+        tag: u64,
+    }
+
+    unsafe impl Zeroable for TaggedKey {}
+    unsafe impl Pod for TaggedKey {}
+
+    impl PartialEq for TaggedKey {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl PartialOrd for TaggedKey {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for TaggedKey {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.id.cmp(&other.id)
+        }
+    }
+
+    type Tree = RedBlackTree<TaggedKey, u64, 32>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for id in 0..16 {
+        tree.insert(TaggedKey { id, tag: id * 100 }, id);
+    }
+    let query = TaggedKey { id: 7, tag: 0 };
+    let (key, value) = tree.get_key_value(&query).unwrap();
+    assert_eq!((key.id, key.tag, *value), (7, 700, 7));
+    assert!(tree.contains_key(&query));
+    let missing = TaggedKey { id: 16, tag: 700 };
+    assert!(tree.get_key_value(&missing).is_none());
+    assert!(!tree.contains_key(&missing));
+}
+
+#[test]
+fn test_clear() {
+    use rand::Rng;
+    type Tree = RedBlackTree<u64, u64, 128>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for _ in 0..3 {
+        while !tree.is_full() {
+            tree.insert(rng.gen(), rng.gen());
+        }
+        // Free some slots so the refill goes through both the free list and the bump index
+        for _ in 0..32 {
+            let key = *tree.iter().nth(rng.gen_range(0, tree.len())).unwrap().0;
+            tree.remove(&key);
+        }
+        let version = tree.version();
+        tree.clear();
+        assert_eq!(tree.root, SENTINEL);
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.iter().count(), 0);
+        assert!(tree.is_valid_red_black_tree());
+        assert!(tree.version() > version);
+        assert_eq!(tree.remaining_capacity(), tree.capacity());
+    }
+    for k in 0..tree.capacity() as u64 {
+        tree.insert(k, k);
+    }
+    assert!(tree.is_full());
+    assert!(tree.is_valid_red_black_tree());
+    assert_eq!(tree.rank(&64), 64);
+    assert!(tree.iter().map(|(k, _)| *k).eq(0..128));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "Inconsistent Ord")]
+fn test_inconsistent_ord_is_detected() {
+    /// Keys of 100 or more claim to be less than every key, so `Ord` is not antisymmetric
+    #[repr(C)]
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+    struct BrokenKey(u64);
+
+    unsafe impl Zeroable for BrokenKey {}
+    unsafe impl Pod for BrokenKey {}
+
+    impl PartialOrd for BrokenKey {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for BrokenKey {
+        fn cmp(&self, other: &Self) -> Ordering {
+            if self.0 < 100 {
+                self.0.cmp(&other.0)
+            } else {
+                Ordering::Less
+            }
+        }
+    }
+
+    type Tree = RedBlackTree<BrokenKey, u64, 32>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for k in 0..10 {
+        tree.insert(BrokenKey(k), k);
+    }
+    tree.insert(BrokenKey(200), 200);
+}
+
+#[test]
+fn test_insert_new() {
+    type Tree = RedBlackTree<u64, u64, 8>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    let index = tree.insert_new(1, 10).unwrap();
+    assert_eq!(tree.get_node(index).value, 10);
+    let version = tree.version();
+    assert_eq!(tree.insert_new(1, 20), Err((1, 10)));
+    assert_eq!(tree.get(&1), Some(&10));
+    assert_eq!(tree.version(), version);
+    for k in 2..9 {
+        assert!(tree.insert_new(k, k * 10).is_ok());
+    }
+    assert!(tree.is_full());
+    assert_eq!(tree.insert_new(9, 90), Err((9, 90)));
+    assert_eq!(tree.insert_new(5, 0), Err((5, 50)));
+    assert_eq!(tree.len(), 8);
+    assert!(tree.is_valid_red_black_tree());
+}
+
+#[test]
+fn test_unboxed_iter_mut() {
+    type Tree = RedBlackTree<u64, u64, 64>;
+    let mut tree = Tree::new();
+    for k in 0..64 {
+        tree.insert(k, k);
+    }
+    let mut iter: RedBlackTreeIteratorMut<'_, u64, u64, 64> = tree.iter_mut();
+    for (k, v) in iter.by_ref().take(32) {
+        *v = k * 2;
+    }
+    for (k, v) in iter.rev() {
+        *v = k * 3;
+    }
+    assert!(tree
+        .iter()
+        .all(|(k, v)| *v == if *k < 32 { k * 2 } else { k * 3 }));
+    // The boxed trait iterator visits the same entries
+    assert!(NodeAllocatorMap::iter(&tree).eq(tree.iter()));
+}
+
+#[test]
+fn test_rebuild_subtree_sizes() {
+    use rand::Rng;
+    type Tree = RedBlackTree<u64, u64, 256>;
+    let mut rng = rand::thread_rng();
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    for _ in 0..256 {
+        tree.insert(rng.gen_range(0, 1000), 0);
+    }
+    let keys = tree.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+    // Emulate a tree written before subtree sizes were stored by keeping only the color bit
+    for key in keys.iter() {
+        let node = tree.get_addr(key);
+        let color = tree.get_color(node);
+        tree.allocator.set_field(node, color, COLOR);
+    }
+    assert!(!tree.is_valid_red_black_tree());
+    assert_eq!(tree.subtree_size(tree.root), 0);
+
+    tree.rebuild_subtree_sizes();
+    assert!(tree.is_valid_red_black_tree());
+    assert_eq!(tree.subtree_size(tree.root), keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(tree.select(i).map(|(k, _)| *k), Some(*key));
+        assert_eq!(tree.rank(key), i);
+    }
+    // Later mutations keep the rebuilt sizes up to date
+    tree.remove(&keys[0]);
+    tree.insert(1000, 0);
+    assert!(tree.is_valid_red_black_tree());
+    assert_eq!(tree.rank(&1000), keys.len() - 1);
+}
+
+#[test]
+fn test_remove_missing_key_prints_nothing() {
+    type Tree = RedBlackTree<u64, u64, 64>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+    let printed = crate::PRINTED_LINES.with(|lines| lines.get());
+    assert_eq!(tree.remove(&1), None);
+    for k in 0..32 {
+        tree.insert(k * 2, k);
+    }
+    for k in 0..32 {
+        assert_eq!(tree.remove(&(k * 2 + 1)), None);
+    }
+    assert_eq!(tree.remove(&1000), None);
+    assert_eq!(tree.len(), 32);
+    assert_eq!(crate::PRINTED_LINES.with(|lines| lines.get()), printed);
+    // Printing the tree on purpose is counted
+    tree.pretty_print();
+    assert_eq!(crate::PRINTED_LINES.with(|lines| lines.get()), printed + 1);
+}
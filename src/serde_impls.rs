@@ -0,0 +1,349 @@
+//! `Serialize` and `Deserialize` implementations, enabled by the `serde` feature.
+//!
+//! Containers are serialized as their logical contents rather than their raw allocator layout:
+//! maps become a sequence of `(key, value)` pairs (in key order for the trees) and a `Deque`
+//! becomes a sequence of its elements from front to back. Deserializing rebuilds an empty
+//! container with `insert` or `push_back`, and fails if the input holds more entries than the
+//! container's `MAX_SIZE`.
+
+use crate::node_allocator::NodeAllocatorMap;
+use crate::{AVLTree, Critbit, CritbitKey, Deque, HashTable, RedBlackTree};
+use bytemuck::{Pod, Zeroable};
+use core::fmt::{self, Debug};
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+/// A container that can be rebuilt one entry at a time during deserialization
+trait Rebuild: Sized {
+    type Entry;
+
+    const CAPACITY: usize;
+
+    fn empty() -> Self;
+
+    /// Adds `entry` to the container, returning false if there was no room for it
+    fn push(&mut self, entry: Self::Entry) -> bool;
+}
+
+struct RebuildVisitor<C>(PhantomData<C>);
+
+impl<'de, C: Rebuild> Visitor<'de> for RebuildVisitor<C>
+where
+    C::Entry: Deserialize<'de>,
+{
+    type Value = C;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a sequence of at most {} entries", C::CAPACITY)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<C, A::Error> {
+        let mut container = C::empty();
+        let mut len = 0;
+        while let Some(entry) = seq.next_element()? {
+            len += 1;
+            if !container.push(entry) {
+                return Err(A::Error::invalid_length(len, &self));
+            }
+        }
+        Ok(container)
+    }
+}
+
+fn rebuild<'de, C: Rebuild, D: Deserializer<'de>>(deserializer: D) -> Result<C, D::Error>
+where
+    C::Entry: Deserialize<'de>,
+{
+    deserializer.deserialize_seq(RebuildVisitor::<C>(PhantomData))
+}
+
+impl<
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable + Serialize,
+        V: Default + Copy + Clone + Pod + Zeroable + Serialize,
+        const MAX_SIZE: usize,
+    > Serialize for RedBlackTree<K, V, MAX_SIZE>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > Rebuild for RedBlackTree<K, V, MAX_SIZE>
+{
+    type Entry = (K, V);
+    const CAPACITY: usize = MAX_SIZE;
+
+    fn empty() -> Self {
+        Self::new()
+    }
+
+    fn push(&mut self, (key, value): (K, V)) -> bool {
+        self.insert(key, value).is_some()
+    }
+}
+
+impl<
+        'de,
+        K: Debug + PartialOrd + Ord + Copy + Clone + Default + Pod + Zeroable + Deserialize<'de>,
+        V: Default + Copy + Clone + Pod + Zeroable + Deserialize<'de>,
+        const MAX_SIZE: usize,
+    > Deserialize<'de> for RedBlackTree<K, V, MAX_SIZE>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        rebuild(deserializer)
+    }
+}
+
+impl<
+        K: PartialOrd + Copy + Clone + Default + Pod + Zeroable + Serialize,
+        V: Default + Copy + Clone + Pod + Zeroable + Serialize,
+        const MAX_SIZE: usize,
+    > Serialize for AVLTree<K, V, MAX_SIZE>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<
+        K: PartialOrd + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const MAX_SIZE: usize,
+    > Rebuild for AVLTree<K, V, MAX_SIZE>
+{
+    type Entry = (K, V);
+    const CAPACITY: usize = MAX_SIZE;
+
+    fn empty() -> Self {
+        Self::new()
+    }
+
+    fn push(&mut self, (key, value): (K, V)) -> bool {
+        self.insert(key, value).is_some()
+    }
+}
+
+impl<
+        'de,
+        K: PartialOrd + Copy + Clone + Default + Pod + Zeroable + Deserialize<'de>,
+        V: Default + Copy + Clone + Pod + Zeroable + Deserialize<'de>,
+        const MAX_SIZE: usize,
+    > Deserialize<'de> for AVLTree<K, V, MAX_SIZE>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        rebuild(deserializer)
+    }
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable + Serialize,
+        V: Default + Copy + Clone + Pod + Zeroable + Serialize,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Serialize for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable,
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Rebuild for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    type Entry = (K, V);
+    const CAPACITY: usize = MAX_SIZE;
+
+    fn empty() -> Self {
+        Self::new()
+    }
+
+    fn push(&mut self, (key, value): (K, V)) -> bool {
+        self.insert(key, value).is_some()
+    }
+}
+
+impl<
+        'de,
+        K: Hash + PartialEq + Copy + Clone + Default + Pod + Zeroable + Deserialize<'de>,
+        V: Default + Copy + Clone + Pod + Zeroable + Deserialize<'de>,
+        const NUM_BUCKETS: usize,
+        const MAX_SIZE: usize,
+        S: BuildHasher + Default + 'static,
+    > Deserialize<'de> for HashTable<K, V, NUM_BUCKETS, MAX_SIZE, S>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        rebuild(deserializer)
+    }
+}
+
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable + Serialize,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey + Serialize,
+    > Serialize for Critbit<V, NUM_NODES, MAX_SIZE, K>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<
+        V: Default + Copy + Clone + Pod + Zeroable,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey,
+    > Rebuild for Critbit<V, NUM_NODES, MAX_SIZE, K>
+{
+    type Entry = (K, V);
+    const CAPACITY: usize = MAX_SIZE;
+
+    fn empty() -> Self {
+        Self::new()
+    }
+
+    fn push(&mut self, (key, value): (K, V)) -> bool {
+        self.insert(key, value).is_some()
+    }
+}
+
+impl<
+        'de,
+        V: Default + Copy + Clone + Pod + Zeroable + Deserialize<'de>,
+        const NUM_NODES: usize,
+        const MAX_SIZE: usize,
+        K: CritbitKey + Deserialize<'de>,
+    > Deserialize<'de> for Critbit<V, NUM_NODES, MAX_SIZE, K>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        rebuild(deserializer)
+    }
+}
+
+impl<T: Default + Copy + Clone + Pod + Zeroable + Serialize, const MAX_SIZE: usize> Serialize
+    for Deque<T, MAX_SIZE>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(|(_, value)| value))
+    }
+}
+
+impl<T: Default + Copy + Clone + Pod + Zeroable, const MAX_SIZE: usize> Rebuild
+    for Deque<T, MAX_SIZE>
+{
+    type Entry = T;
+    const CAPACITY: usize = MAX_SIZE;
+
+    fn empty() -> Self {
+        Self::new()
+    }
+
+    fn push(&mut self, value: T) -> bool {
+        if self.len() == MAX_SIZE {
+            return false;
+        }
+        self.push_back(value);
+        true
+    }
+}
+
+impl<'de, T: Default + Copy + Clone + Pod + Zeroable + Deserialize<'de>, const MAX_SIZE: usize>
+    Deserialize<'de> for Deque<T, MAX_SIZE>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        rebuild(deserializer)
+    }
+}
+
+#[test]
+fn test_round_trip_maps() {
+    fn entries<K: Copy, V: Copy>(map: &dyn NodeAllocatorMap<K, V>) -> Vec<(K, V)> {
+        map.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    let mut rbt = RedBlackTree::<u64, u64, 64>::new();
+    let mut avl = AVLTree::<u64, u64, 64>::new();
+    let mut table = HashTable::<u64, u64, 16, 64>::new();
+    let mut critbit = Critbit::<u64, 128, 64>::new();
+    for i in 0..48u64 {
+        let k = (i * 29) % 64;
+        rbt.insert(k, i);
+        avl.insert(k, i);
+        table.insert(k, i);
+        critbit.insert(k as u128, i);
+    }
+
+    let json = serde_json::to_string(&rbt).unwrap();
+    let rbt2: RedBlackTree<u64, u64, 64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(entries(&rbt2), entries(&rbt));
+    assert!(rbt2.is_valid_red_black_tree());
+
+    let json = serde_json::to_string(&avl).unwrap();
+    // Trees are serialized in key order
+    assert_eq!(
+        json,
+        serde_json::to_string(&rbt.iter().collect::<Vec<_>>()).unwrap()
+    );
+    let avl2: AVLTree<u64, u64, 64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(entries(&avl2), entries(&avl));
+    assert!(avl2.is_valid_avl_tree());
+
+    let json = serde_json::to_string(&table).unwrap();
+    let table2: HashTable<u64, u64, 16, 64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(table2.len(), table.len());
+    for (k, v) in table.iter() {
+        assert_eq!(table2.get(k), Some(v));
+    }
+
+    let json = serde_json::to_string(&critbit).unwrap();
+    let critbit2: Critbit<u64, 128, 64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(entries(&critbit2), entries(&critbit));
+}
+
+#[test]
+fn test_round_trip_deque() {
+    let mut deque = Deque::<u64, 16>::new();
+    for i in 0..8 {
+        deque.push_back(i);
+        deque.push_front(100 + i);
+    }
+    let json = serde_json::to_string(&deque).unwrap();
+    assert_eq!(
+        json,
+        "[107,106,105,104,103,102,101,100,0,1,2,3,4,5,6,7]".to_string()
+    );
+    let deque2: Deque<u64, 16> = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        deque2.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        deque.iter().map(|(_, v)| *v).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_deserialize_over_capacity() {
+    let json = serde_json::to_string(&(0..9u64).map(|i| (i, i)).collect::<Vec<_>>()).unwrap();
+    let err = serde_json::from_str::<RedBlackTree<u64, u64, 8>>(&json).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("invalid length 9, expected a sequence of at most 8 entries"));
+    assert!(serde_json::from_str::<AVLTree<u64, u64, 8>>(&json).is_err());
+    assert!(serde_json::from_str::<HashTable<u64, u64, 4, 8>>(&json).is_err());
+    assert!(serde_json::from_str::<Critbit<u64, 16, 8>>(&json).is_err());
+    assert!(serde_json::from_str::<RedBlackTree<u64, u64, 9>>(&json).is_ok());
+
+    let json = serde_json::to_string(&(0..9u64).collect::<Vec<_>>()).unwrap();
+    assert!(serde_json::from_str::<Deque<u64, 8>>(&json).is_err());
+    assert!(serde_json::from_str::<Deque<u64, 9>>(&json).is_ok());
+}
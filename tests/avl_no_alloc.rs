@@ -0,0 +1,60 @@
+#![cfg(not(miri))]
+
+use sokoban::node_allocator::FromSlice;
+use sokoban::node_allocator::NodeAllocatorMap;
+use sokoban::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Counts the allocations made on the current thread so that allocations
+// made by the test harness on other threads are ignored
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+#[test]
+fn test_avl_insert_remove_do_not_allocate() {
+    type Tree = AVLTree<u64, u64, 1024>;
+    let mut buf = vec![0u8; std::mem::size_of::<Tree>()];
+    let tree = Tree::new_from_slice(buf.as_mut_slice());
+
+    // a fixed permutation of 0..1024 so that every rebalancing case is exercised
+    let keys = (0..1024u64).map(|i| (i * 769) % 1024).collect::<Vec<_>>();
+
+    let before = allocations();
+    for &k in keys.iter() {
+        assert!(tree.insert(k, k).is_some());
+    }
+    assert!(tree.insert(1024, 1024).is_none());
+    for &k in keys.iter().step_by(2) {
+        assert_eq!(tree.remove(&k), Some(k));
+    }
+    for &k in keys.iter().step_by(2) {
+        assert!(tree.insert(k, k + 1).is_some());
+    }
+    for &k in keys.iter().rev() {
+        assert!(tree.remove(&k).is_some());
+    }
+    assert_eq!(allocations(), before);
+    assert_eq!(tree.len(), 0);
+}